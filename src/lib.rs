@@ -1,4 +1,5 @@
 pub mod models;
+pub mod mrz;
 pub mod passport_validator;
 pub mod processing;
 pub mod utils;
@@ -1,3 +1,6 @@
+use chrono::NaiveDate;
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum DocumentFormat {
     TD1,  // ID Card (85.6mm × 54.0mm)
@@ -37,24 +40,378 @@ impl DocumentFormat {
             DocumentFormat::MRVB => 36,
         }
     }
+
+    /// Visas carry optional data in place of the composite check digit
+    pub fn has_composite_check(&self) -> bool {
+        !self.is_visa()
+    }
+
+    pub fn is_visa(&self) -> bool {
+        matches!(self, DocumentFormat::MRVA | DocumentFormat::MRVB)
+    }
+}
+
+/// Kind of document, given by the first letter of the document code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentCategory {
+    Passport,     // P
+    IdentityCard, // I, A or C: official travel documents in card format
+    Visa,         // V
+}
+
+/// Meaning of the second letter of the document code; issuers may assign their own letters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentSubtype {
+    Ordinary,        // No second letter (filler)
+    Diplomatic,      // PD
+    Official,        // PO
+    Service,         // PS
+    Emergency,       // PE
+    TravelDocument,  // PT: travel document for refugees, stateless persons or aliens
+    CrewMember,      // AC: crew member certificate
+    ResidencePermit, // IR, IP, AR
+    Other(char),     // Issuer-specific letter
+}
+
+/// Document code from the first two MRZ characters (e.g. "P<", "PD", "AC")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentType {
+    pub category: DocumentCategory,
+    pub subtype: DocumentSubtype,
+    pub code: String, // Code without trailing filler, e.g. "P" or "PD"
+}
+
+impl DocumentType {
+    /// Parse a document code with or without its trailing filler; None if the first letter
+    /// is not a Doc 9303 document letter (P, I, A, C, V)
+    pub fn parse(code: &str) -> Option<DocumentType> {
+        let code = code.trim_end_matches('<');
+        let mut chars = code.chars();
+        let first = chars.next()?;
+        let second = chars.next();
+        if chars.next().is_some() || second.is_some_and(|c| !c.is_ascii_uppercase()) {
+            return None;
+        }
+
+        let category = match first {
+            'P' => DocumentCategory::Passport,
+            'I' | 'A' | 'C' => DocumentCategory::IdentityCard,
+            'V' => DocumentCategory::Visa,
+            _ => return None,
+        };
+        let subtype = match (first, second) {
+            (_, None) => DocumentSubtype::Ordinary,
+            ('P', Some('D')) => DocumentSubtype::Diplomatic,
+            ('P', Some('O')) => DocumentSubtype::Official,
+            ('P', Some('S')) => DocumentSubtype::Service,
+            ('P', Some('E')) => DocumentSubtype::Emergency,
+            ('P', Some('T')) => DocumentSubtype::TravelDocument,
+            ('A', Some('C')) => DocumentSubtype::CrewMember,
+            ('I', Some('R')) | ('I', Some('P')) | ('A', Some('R')) => DocumentSubtype::ResidencePermit,
+            (_, Some(c)) => DocumentSubtype::Other(c),
+        };
+
+        Some(DocumentType {
+            category,
+            subtype,
+            code: code.to_string(),
+        })
+    }
+
+    /// Whether documents of this category are issued in the given MRZ format
+    pub fn fits_format(&self, format: &DocumentFormat) -> bool {
+        match format {
+            DocumentFormat::TD1 | DocumentFormat::TD2 => self.category == DocumentCategory::IdentityCard,
+            DocumentFormat::TD3 => self.category == DocumentCategory::Passport,
+            DocumentFormat::MRVA | DocumentFormat::MRVB => self.category == DocumentCategory::Visa,
+        }
+    }
+}
+
+impl fmt::Display for DocumentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code)
+    }
+}
+
+/// Sex of the holder as defined by ICAO Doc 9303
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sex {
+    Male,
+    Female,
+    Unspecified, // X in the VIZ, filler (or X) in the MRZ
+}
+
+impl Sex {
+    /// Sex from the MRZ sex character; None if the character is not M, F, X or a filler
+    pub fn from_mrz(c: char) -> Option<Sex> {
+        match c {
+            'M' => Some(Sex::Male),
+            'F' => Some(Sex::Female),
+            '<' | 'X' => Some(Sex::Unspecified),
+            _ => None,
+        }
+    }
+
+    /// Character encoding this sex in the MRZ
+    pub fn to_mrz(&self) -> char {
+        match self {
+            Sex::Male => 'M',
+            Sex::Female => 'F',
+            Sex::Unspecified => '<',
+        }
+    }
+
+    /// Sex from a localized VIZ value such as "F", "H" (hombre, homme), "W" (weiblich), "Ж" or "男".
+    ///
+    /// Bilingual values ("F/W", "M/H") are accepted when every part agrees. None if the value is
+    /// not recognized or the parts disagree.
+    pub fn from_viz_label(label: &str) -> Option<Sex> {
+        let mut sex = None;
+        for part in label.split('/') {
+            let part = part.trim_matches(|c: char| !c.is_alphanumeric()).to_uppercase();
+            if part.is_empty() {
+                continue;
+            }
+            let part_sex = Self::viz_label(&part)?;
+            if sex.is_some_and(|s| s != part_sex) {
+                return None;
+            }
+            sex = Some(part_sex);
+        }
+        sex
+    }
+
+    /// Single uppercase VIZ value in one of the common passport languages
+    fn viz_label(label: &str) -> Option<Sex> {
+        match label {
+            "M" | "MALE" | "H" | "HOMBRE" | "HOMME" | "MASCULIN" | "MASCULINO" | "MASCHILE" | "MÄNNLICH" | "М"
+            | "МУЖ" | "МУЖСКОЙ" | "男" => Some(Sex::Male),
+            "F" | "FEMALE" | "MUJER" | "FEMME" | "FÉMININ" | "FEMININ" | "FEMENINO" | "FEMININO" | "FEMMINILE" | "W"
+            | "WEIBLICH" | "K" | "Ж" | "ЖЕН" | "ЖЕНСКИЙ" | "女" => Some(Sex::Female),
+            "X" | "<" | "UNSPECIFIED" | "NON SPÉCIFIÉ" | "UNBESTIMMT" => Some(Sex::Unspecified),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Sex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sex::Male => write!(f, "M"),
+            Sex::Female => write!(f, "F"),
+            Sex::Unspecified => write!(f, "X"),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct MrzData {
     pub document_format: Option<DocumentFormat>,
-    pub document_type: String,
+    pub document_type: Option<DocumentType>, // None when unreadable
     pub issuing_country: String,
     pub document_number: String,
     pub surname: String,
     pub given_names: String,
     pub nationality: String,
-    pub date_of_birth: String,
-    pub gender: String,
-    pub date_of_expiry: String,
+    pub date_of_birth: DocumentDate,
+    pub sex: Option<Sex>, // None when unreadable
+    pub date_of_expiry: DocumentDate,
     pub personal_number: Option<String>,
     pub optional_data: Option<String>,
+    pub optional_data_2: Option<String>, // Second optional data field (TD1 line 2)
     pub check_digits: CheckDigits,
     pub raw_mrz_lines: Vec<String>,
+    pub corrections: Vec<CharacterCorrection>, // OCR errors fixed using check digits
+    pub unreadable: Vec<UnreadableField>,      // Fields left empty because they could not be read
+    pub repairs: Vec<FieldRepair>,             // Values changed by lenient parsing or line re-alignment
+    pub confidences: Vec<FieldConfidence>,     // OCR confidence per field, empty when not read by OCR
+}
+
+impl MrzData {
+    /// OCR confidence of a field, by the label used in issues and repairs (e.g. "Document number")
+    pub fn confidence(&self, field: &str) -> Option<&FieldConfidence> {
+        self.confidences.iter().find(|c| c.field == field)
+    }
+
+    /// Whether a field (by its label, e.g. "Date of birth") could not be read
+    pub fn is_unreadable(&self, field: &str) -> bool {
+        self.unreadable.iter().any(|u| u.field == field)
+    }
+}
+
+/// An MRZ field that could not be read; its value in `MrzData` is left empty
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnreadableField {
+    pub field: String,
+    pub reason: String,
+}
+
+/// OCR confidence of one character of a field
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharacterConfidence {
+    pub position: usize, // 0-based position within the field
+    pub character: char,
+    pub confidence: f32, // 0 to 100
+}
+
+/// OCR confidence of a field: the mean confidence of its characters and the characters read
+/// with less than `FieldConfidence::UNCERTAIN_BELOW`, least confident first
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldConfidence {
+    pub field: String,
+    pub confidence: f32, // 0 to 100
+    pub uncertain: Vec<CharacterConfidence>,
+}
+
+impl FieldConfidence {
+    /// Characters read with a lower confidence are reported as uncertain
+    pub const UNCERTAIN_BELOW: f32 = 80.0;
+
+    /// Confidence of a field from the confidence of each of its characters
+    pub fn new(field: &str, characters: &[(char, f32)]) -> Self {
+        let confidence = if characters.is_empty() {
+            0.0
+        } else {
+            characters.iter().map(|(_, confidence)| confidence).sum::<f32>() / characters.len() as f32
+        };
+        let mut uncertain: Vec<CharacterConfidence> = characters
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, confidence))| *confidence < Self::UNCERTAIN_BELOW)
+            .map(|(position, (character, confidence))| CharacterConfidence {
+                position,
+                character: *character,
+                confidence: *confidence,
+            })
+            .collect();
+        uncertain.sort_by(|a, b| a.confidence.total_cmp(&b.confidence));
+
+        FieldConfidence {
+            field: field.to_string(),
+            confidence,
+            uncertain,
+        }
+    }
+
+    /// Least confident character of the field, if any is uncertain
+    pub fn lowest(&self) -> Option<&CharacterConfidence> {
+        self.uncertain.first()
+    }
+}
+
+impl fmt::Display for FieldConfidence {
+    /// e.g. "Document number read with 62% confidence, position 4 uncertain"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} read with {:.0}% confidence", self.field, self.confidence)?;
+        if !self.uncertain.is_empty() {
+            let positions: Vec<String> = self.uncertain.iter().map(|c| (c.position + 1).to_string()).collect();
+            let noun = if positions.len() == 1 { "position" } else { "positions" };
+            write!(f, ", {} {} uncertain", noun, positions.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A value rewritten by lenient parsing or MRZ line re-alignment, with the text it was read from
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldRepair {
+    pub field: String,
+    pub original: String,
+    pub repaired: String,
+    pub reason: String,
+}
+
+/// A date read from the MRZ or VIZ.
+///
+/// ICAO Doc 9303 allows the day or month to be unknown (`<<` in the MRZ, usually `XX` in
+/// the VIZ), so every component is optional. The text the date was read from is kept
+/// alongside the resolved components.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentDate {
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+    pub raw: String, // MRZ YYMMDD or VIZ text, empty when not read from a document
+}
+
+impl DocumentDate {
+    pub fn new(year: Option<i32>, month: Option<u32>, day: Option<u32>, raw: &str) -> Self {
+        DocumentDate {
+            year,
+            month,
+            day,
+            raw: raw.to_string(),
+        }
+    }
+
+    pub fn from_ymd(year: i32, month: u32, day: u32) -> Self {
+        Self::new(Some(year), Some(month), Some(day), "")
+    }
+
+    /// No component is known
+    pub fn is_empty(&self) -> bool {
+        self.year.is_none() && self.month.is_none() && self.day.is_none()
+    }
+
+    /// Year, month and day are all known
+    pub fn is_complete(&self) -> bool {
+        self.year.is_some() && self.month.is_some() && self.day.is_some()
+    }
+
+    /// Calendar date, if the date is complete and valid
+    pub fn to_naive_date(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year?, self.month?, self.day?)
+    }
+
+    /// Last calendar day the date may denote; an unknown day or month extends to the end
+    /// of the month or year
+    pub fn latest_date(&self) -> Option<NaiveDate> {
+        let year = self.year?;
+        let month = self.month.unwrap_or(12);
+        match self.day {
+            Some(day) => NaiveDate::from_ymd_opt(year, month, day),
+            None => {
+                let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                NaiveDate::from_ymd_opt(next_year, next_month, 1)?.pred_opt()
+            }
+        }
+    }
+
+    /// Same year, month and day (known or unknown), regardless of the text they were read from
+    pub fn same_date(&self, other: &DocumentDate) -> bool {
+        self.year == other.year && self.month == other.month && self.day == other.day
+    }
+
+    /// MRZ YYMMDD form with `<<` for an unknown day or month, or None without a year
+    pub fn to_mrz(&self) -> Option<String> {
+        let component = |value: Option<u32>| value.map_or("<<".to_string(), |v| format!("{:02}", v));
+        Some(format!(
+            "{:02}{}{}",
+            self.year?.rem_euclid(100),
+            component(self.month),
+            component(self.day)
+        ))
+    }
+}
+
+impl fmt::Display for DocumentDate {
+    /// "DD MM YYYY", with `XX` for unknown components
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let component = |value: Option<u32>| value.map_or("XX".to_string(), |v| format!("{:02}", v));
+        let year = self.year.map_or("XXXX".to_string(), |y| format!("{:04}", y));
+        write!(f, "{} {} {}", component(self.day), component(self.month), year)
+    }
+}
+
+/// A single MRZ character replaced because the corrected value satisfies its check digit
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharacterCorrection {
+    pub field: String,
+    pub line: usize,     // 0-based MRZ line
+    pub position: usize, // 0-based character position in the line
+    pub original: char,
+    pub corrected: char,
 }
 
 #[derive(Debug, Clone)]
@@ -116,24 +473,32 @@ pub struct ChipData {
 #[derive(Debug, Clone)]
 pub struct VisualData {
     pub document_format: Option<DocumentFormat>,
-    pub document_type: String,
+    pub document_type: Option<DocumentType>,
     pub issuing_country: String,
     pub document_number: String,
     pub name: String,
     pub surname: String,
     pub given_names: String,
     pub nationality: String,
-    pub date_of_birth: String,
-    pub gender: String,
+    pub date_of_birth: DocumentDate,
+    pub sex: Option<Sex>, // None when neither the VIZ nor the MRZ gives it
     pub place_of_birth: Option<String>,
-    pub date_of_issue: String,
-    pub date_of_expiry: String,
+    pub date_of_issue: DocumentDate,
+    pub date_of_expiry: DocumentDate,
     pub authority: Option<String>,
     pub personal_number: Option<String>,
     pub portrait: Option<Vec<u8>>,
     pub signature: Option<Vec<u8>>,
     pub secondary_portrait: Option<Vec<u8>>, // Ghost image
     pub additional_fields: std::collections::HashMap<String, String>,
+    pub confidences: Vec<FieldConfidence>, // OCR confidence per field; fields taken from the MRZ keep its confidence
+}
+
+impl VisualData {
+    /// OCR confidence of a field, by label (e.g. "Place of birth")
+    pub fn confidence(&self, field: &str) -> Option<&FieldConfidence> {
+        self.confidences.iter().find(|c| c.field == field)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -184,7 +549,9 @@ pub struct MrzValidationResult {
     pub date_of_birth_check_valid: bool,
     pub date_of_expiry_check_valid: bool,
     pub personal_number_check_valid: bool,
+    pub sex_check_valid: Option<bool>, // None when the MRZ or VIZ sex is unknown, so nothing was compared
     pub composite_check_valid: bool,
+    pub corrections: Vec<CharacterCorrection>, // Characters changed to satisfy check digits; checks passed on the corrected MRZ
     pub issues: Vec<ValidationIssue>,
 }
 
@@ -0,0 +1,293 @@
+use crate::models::{DocumentFormat, FieldRepair};
+use crate::mrz::{CheckDigitCalculator, CountryRegistry, MrzCharset, MrzLayout, MrzSpan};
+
+/// An edit made to bring an OCR'd line back to its layout length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentEdit {
+    /// A filler inserted where a character was dropped (position in the aligned line)
+    Inserted { line: usize, position: usize },
+    /// A stray character removed (position in the line as read)
+    Removed { line: usize, position: usize, character: char },
+}
+
+impl AlignmentEdit {
+    /// The edit as a repair of its MRZ line
+    pub fn to_repair(&self) -> FieldRepair {
+        match *self {
+            AlignmentEdit::Inserted { line, position } => FieldRepair {
+                field: format!("MRZ line {}", line + 1),
+                original: String::new(),
+                repaired: "<".to_string(),
+                reason: format!("filler inserted at position {} for a dropped character", position + 1),
+            },
+            AlignmentEdit::Removed { line, position, character } => FieldRepair {
+                field: format!("MRZ line {}", line + 1),
+                original: character.to_string(),
+                repaired: String::new(),
+                reason: format!("stray character removed at position {}", position + 1),
+            },
+        }
+    }
+}
+
+/// MRZ lines re-synchronized to the canonical layout of their format
+#[derive(Debug, Clone)]
+pub struct AlignedMrz {
+    pub lines: Vec<String>,
+    pub edits: Vec<AlignmentEdit>,
+}
+
+/// Re-aligns MRZ lines that are a character or two short or long.
+///
+/// A dropped or inserted character shifts every following field, so padding or truncating at
+/// the end only helps when the error is at the very end. Instead, every way of inserting
+/// fillers into (or removing characters from) the line is tried and scored against structural
+/// anchors of the layout: check digits, country codes, plausible dates and the charset of
+/// every position. Among equally good alignments the one editing closest to the end wins.
+pub struct MrzAligner;
+
+impl MrzAligner {
+    /// Lines off by more than this many characters are padded or truncated at the end
+    const MAX_EDITS: usize = 2;
+
+    const CHECK_DIGIT_WEIGHT: i32 = 4;
+    const COUNTRY_CODE_WEIGHT: i32 = 2;
+    const DATE_WEIGHT: i32 = 1;
+    const CHARSET_MISMATCH_WEIGHT: i32 = 1;
+
+    /// Align every line to the line length of the format
+    pub fn align(lines: &[&str], format: &DocumentFormat) -> AlignedMrz {
+        let line_length = format.mrz_chars_per_line();
+        let mut aligned: Vec<Vec<char>> = lines.iter().map(|l| Self::fit(l, line_length)).collect();
+        let mut edits = Vec::new();
+        let charsets = Self::position_charsets(format);
+
+        for (index, line) in lines.iter().enumerate().take(format.mrz_lines()) {
+            let chars: Vec<char> = line.chars().collect();
+            let excess = chars.len() as isize - line_length as isize;
+            if excess == 0 || excess.unsigned_abs() > Self::MAX_EDITS {
+                continue;
+            }
+
+            let mut best: Option<(i32, usize, Vec<char>, Vec<AlignmentEdit>)> = None;
+            for (variant, variant_edits) in Self::variants(&chars, index, excess, 0) {
+                aligned[index] = variant;
+                let score = Self::score(&aligned, format, &charsets);
+                // Later edits win ties, matching the common case of a filler lost at the end
+                let position_sum = variant_edits.iter().map(Self::edit_position).sum();
+                if best.as_ref().is_none_or(|(s, p, _, _)| (score, position_sum) > (*s, *p)) {
+                    best = Some((score, position_sum, aligned[index].clone(), variant_edits));
+                }
+            }
+            if let Some((_, _, variant, variant_edits)) = best {
+                aligned[index] = variant;
+                edits.extend(variant_edits);
+            }
+        }
+
+        AlignedMrz {
+            lines: aligned.into_iter().map(|l| l.into_iter().collect()).collect(),
+            edits,
+        }
+    }
+
+    /// Apply the edits of one line to values parallel to its characters (e.g. OCR confidences),
+    /// using `filler` at inserted positions and fitting the result to the line length as `align` does
+    pub fn apply_edits<T: Clone>(
+        values: &[T],
+        line: usize,
+        edits: &[AlignmentEdit],
+        filler: T,
+        line_length: usize,
+    ) -> Vec<T> {
+        let mut values = values.to_vec();
+        // Removal positions refer to the line as read, so remove from the end
+        let mut removed: Vec<usize> = edits
+            .iter()
+            .filter_map(|edit| match *edit {
+                AlignmentEdit::Removed { line: l, position, .. } if l == line => Some(position),
+                _ => None,
+            })
+            .collect();
+        removed.sort_unstable_by(|a, b| b.cmp(a));
+        for position in removed {
+            if position < values.len() {
+                values.remove(position);
+            }
+        }
+        // Insertion positions refer to the line with the earlier insertions applied
+        for edit in edits {
+            if let AlignmentEdit::Inserted { line: l, position } = *edit {
+                if l == line && position <= values.len() {
+                    values.insert(position, filler.clone());
+                }
+            }
+        }
+        values.resize(line_length, filler);
+        values
+    }
+
+    /// Every line obtained by `excess` removals (or `-excess` filler insertions) at or after `from`
+    fn variants(chars: &[char], line: usize, excess: isize, from: usize) -> Vec<(Vec<char>, Vec<AlignmentEdit>)> {
+        if excess == 0 {
+            return vec![(chars.to_vec(), Vec::new())];
+        }
+
+        let mut variants = Vec::new();
+        if excess > 0 {
+            for position in from..chars.len() {
+                let mut shorter = chars.to_vec();
+                let character = shorter.remove(position);
+                for (variant, mut edits) in Self::variants(&shorter, line, excess - 1, position) {
+                    // Later removals were positioned in the shorter line
+                    for edit in edits.iter_mut() {
+                        if let AlignmentEdit::Removed { position, .. } = edit {
+                            *position += 1;
+                        }
+                    }
+                    edits.insert(0, AlignmentEdit::Removed { line, position, character });
+                    variants.push((variant, edits));
+                }
+            }
+        } else {
+            for position in from..=chars.len() {
+                let mut longer = chars.to_vec();
+                longer.insert(position, '<');
+                for (variant, mut edits) in Self::variants(&longer, line, excess + 1, position + 1) {
+                    edits.insert(0, AlignmentEdit::Inserted { line, position });
+                    variants.push((variant, edits));
+                }
+            }
+        }
+        variants
+    }
+
+    /// How well the lines fit the structural anchors of the layout (higher is better)
+    fn score(lines: &[Vec<char>], format: &DocumentFormat, charsets: &[Vec<MrzCharset>]) -> i32 {
+        let strings: Vec<String> = lines.iter().map(|l| l.iter().collect()).collect();
+        let refs: Vec<&str> = strings.iter().map(|l| l.as_str()).collect();
+        let mut score = 0;
+
+        for rule in MrzLayout::check_digit_rules(format) {
+            let holds = MrzLayout::check_digit_input(&refs, &rule)
+                .is_some_and(|(data, check)| CheckDigitCalculator::verify(&data, check));
+            if holds {
+                score += Self::CHECK_DIGIT_WEIGHT;
+            }
+        }
+
+        for (_, span) in MrzLayout::code_spans(format) {
+            if span.extract(&refs).is_some_and(CountryRegistry::is_valid) {
+                score += Self::COUNTRY_CODE_WEIGHT;
+            }
+        }
+
+        for span in MrzLayout::date_spans(format) {
+            if span.extract(&refs).is_some_and(Self::is_plausible_date) {
+                score += Self::DATE_WEIGHT;
+            }
+        }
+
+        for (line, line_charsets) in lines.iter().zip(charsets) {
+            let mismatches = line.iter().zip(line_charsets).filter(|(c, charset)| !charset.allows(**c)).count();
+            score -= Self::CHARSET_MISMATCH_WEIGHT * mismatches as i32;
+        }
+
+        score
+    }
+
+    /// Position an edit sorts by when breaking ties
+    fn edit_position(edit: &AlignmentEdit) -> usize {
+        match edit {
+            AlignmentEdit::Inserted { position, .. } | AlignmentEdit::Removed { position, .. } => *position,
+        }
+    }
+
+    /// Charset expected at every position of every line
+    fn position_charsets(format: &DocumentFormat) -> Vec<Vec<MrzCharset>> {
+        let mut charsets = vec![vec![MrzCharset::Alphanumeric; format.mrz_chars_per_line()]; format.mrz_lines()];
+        let mut set = |span: MrzSpan, charset: MrzCharset| {
+            if let Some(line) = charsets.get_mut(span.line) {
+                for slot in line.iter_mut().take(span.end).skip(span.start) {
+                    *slot = charset;
+                }
+            }
+        };
+
+        set(MrzSpan::new(0, 0, 2), MrzCharset::Alpha);
+        set(MrzLayout::name_span(format), MrzCharset::Alpha);
+        for (_, span) in MrzLayout::code_spans(format) {
+            set(span, MrzCharset::Alpha);
+        }
+        for span in MrzLayout::date_spans(format) {
+            set(span, MrzCharset::Numeric);
+        }
+        set(MrzLayout::sex_span(format), MrzCharset::Alpha);
+        for rule in MrzLayout::check_digit_rules(format) {
+            set(rule.check, MrzCharset::Numeric);
+        }
+        charsets
+    }
+
+    /// YYMMDD with a month and day in range; `<<` is accepted for an unknown month or day
+    fn is_plausible_date(date: &str) -> bool {
+        let part = |range: std::ops::Range<usize>, max: u32| {
+            let value = &date[range];
+            value == "<<" || value.parse::<u32>().is_ok_and(|v| (1..=max).contains(&v))
+        };
+        date.len() == 6
+            && date.is_ascii()
+            && date[0..2].chars().all(|c| c.is_ascii_digit())
+            && part(2..4, 12)
+            && part(4..6, 31)
+    }
+
+    /// Pad with fillers or truncate a line at the end
+    fn fit(line: &str, line_length: usize) -> Vec<char> {
+        line.chars().chain(std::iter::repeat('<')).take(line_length).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE1: &str = "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<";
+    const LINE2: &str = "L898902C36UTO7408122F1204159ZE184226B<<<<<10";
+
+    #[test]
+    fn removes_a_stray_character() {
+        let read = "L898902C36UTO74X08122F1204159ZE184226B<<<<<10";
+        let aligned = MrzAligner::align(&[LINE1, read], &DocumentFormat::TD3);
+        assert_eq!(aligned.lines, vec![LINE1, LINE2]);
+        assert_eq!(aligned.edits, vec![AlignmentEdit::Removed { line: 1, position: 15, character: 'X' }]);
+    }
+
+    #[test]
+    fn inserts_a_filler_for_a_dropped_character() {
+        // A filler of the personal number is lost, shifting both check digits one place left
+        let read = "L898902C36UTO7408122F1204159ZE184226B<<<<10";
+        let aligned = MrzAligner::align(&[LINE1, read], &DocumentFormat::TD3);
+        assert_eq!(aligned.lines, vec![LINE1, LINE2]);
+        assert_eq!(aligned.edits.len(), 1);
+        assert!(matches!(aligned.edits[0], AlignmentEdit::Inserted { line: 1, position } if (37..42).contains(&position)));
+    }
+
+    #[test]
+    fn leaves_lines_of_the_right_length_alone() {
+        let aligned = MrzAligner::align(&[LINE1, LINE2], &DocumentFormat::TD3);
+        assert_eq!(aligned.lines, vec![LINE1, LINE2]);
+        assert!(aligned.edits.is_empty());
+    }
+
+    #[test]
+    fn applies_edits_to_parallel_values() {
+        let edits = [
+            AlignmentEdit::Removed { line: 0, position: 1, character: 'X' },
+            AlignmentEdit::Inserted { line: 0, position: 3 },
+        ];
+        let values = MrzAligner::apply_edits(&[1, 2, 3, 4, 5], 0, &edits, 0, 6);
+        assert_eq!(values, vec![1, 3, 4, 0, 5, 0]);
+        assert_eq!(edits[1].to_repair().field, "MRZ line 1");
+    }
+}
@@ -0,0 +1,96 @@
+/// ICAO Doc 9303 check digit calculation (Part 3, section 4.9).
+///
+/// Each character is converted to a value (digits as-is, A-Z as 10-35, filler as 0),
+/// multiplied by the repeating weights 7, 3, 1 and the sum is taken modulo 10.
+pub struct CheckDigitCalculator;
+
+impl CheckDigitCalculator {
+    const WEIGHTS: [u32; 3] = [7, 3, 1];
+
+    /// Compute the check digit for an MRZ field, or None if it contains non-MRZ characters
+    pub fn compute(data: &str) -> Option<char> {
+        let mut sum = 0;
+        for (i, c) in data.chars().enumerate() {
+            sum += Self::char_value(c)? * Self::WEIGHTS[i % 3];
+        }
+        char::from_digit(sum % 10, 10)
+    }
+
+    /// Check whether a check digit matches the data it protects.
+    ///
+    /// An empty optional field (all fillers) may carry either `0` or `<` as its check digit.
+    pub fn verify(data: &str, check: char) -> bool {
+        if check == '<' {
+            return data.chars().all(|c| c == '<');
+        }
+        Self::compute(data) == Some(check)
+    }
+
+    /// Numeric value of an MRZ character
+    pub fn char_value(c: char) -> Option<u32> {
+        match c {
+            '0'..='9' => c.to_digit(10),
+            'A'..='Z' => Some(c as u32 - 'A' as u32 + 10),
+            '<' => Some(0),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ICAO Doc 9303 Part 4 specimen, line 2: L898902C36UTO7408122F1204159ZE184226B<<<<<10
+    const SPECIMEN: &str = "L898902C36UTO7408122F1204159ZE184226B<<<<<10";
+
+    #[test]
+    fn computes_specimen_field_check_digits() {
+        assert_eq!(CheckDigitCalculator::compute(&SPECIMEN[0..9]), Some('6'));
+        assert_eq!(CheckDigitCalculator::compute(&SPECIMEN[13..19]), Some('2'));
+        assert_eq!(CheckDigitCalculator::compute(&SPECIMEN[21..27]), Some('9'));
+        assert_eq!(CheckDigitCalculator::compute(&SPECIMEN[28..42]), Some('1'));
+    }
+
+    #[test]
+    fn computes_specimen_composite_check_digit() {
+        let composite = format!("{}{}{}", &SPECIMEN[0..10], &SPECIMEN[13..20], &SPECIMEN[21..43]);
+        assert_eq!(CheckDigitCalculator::compute(&composite), Some('0'));
+        assert!(CheckDigitCalculator::verify(&composite, '0'));
+    }
+
+    #[test]
+    fn rejects_mismatched_check_digits() {
+        assert!(!CheckDigitCalculator::verify("L898902C3", '7'));
+        assert!(!CheckDigitCalculator::verify("740812", '3'));
+        assert!(!CheckDigitCalculator::verify("120415", '0'));
+        assert!(!CheckDigitCalculator::verify("ZE184226B<<<<<", '2'));
+        // A single misread character changes the check digit
+        assert!(!CheckDigitCalculator::verify("L898902C8", '6'));
+    }
+
+    #[test]
+    fn accepts_filler_check_digit_only_for_empty_fields() {
+        assert!(CheckDigitCalculator::verify("<<<<<<<<<<<<<<", '<'));
+        assert!(CheckDigitCalculator::verify("<<<<<<<<<<<<<<", '0'));
+        assert!(!CheckDigitCalculator::verify("ZE184226B<<<<<", '<'));
+    }
+
+    #[test]
+    fn rejects_characters_outside_the_mrz_charset() {
+        assert_eq!(CheckDigitCalculator::compute("l898902c3"), None);
+        assert_eq!(CheckDigitCalculator::compute("L898 902C3"), None);
+        assert_eq!(CheckDigitCalculator::compute("7408-12"), None);
+        assert!(!CheckDigitCalculator::verify("L8989Ö2C3", '6'));
+        assert_eq!(CheckDigitCalculator::char_value('É'), None);
+    }
+
+    #[test]
+    fn maps_characters_to_icao_values() {
+        assert_eq!(CheckDigitCalculator::char_value('0'), Some(0));
+        assert_eq!(CheckDigitCalculator::char_value('9'), Some(9));
+        assert_eq!(CheckDigitCalculator::char_value('A'), Some(10));
+        assert_eq!(CheckDigitCalculator::char_value('Z'), Some(35));
+        assert_eq!(CheckDigitCalculator::char_value('<'), Some(0));
+    }
+}
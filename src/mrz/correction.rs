@@ -0,0 +1,505 @@
+use crate::models::{CharacterCorrection, DocumentFormat};
+use crate::mrz::{CheckDigitCalculator, CheckDigitRule, CheckedField, MrzCharset, MrzLayout};
+
+/// Result of check-digit-guided correction of OCR'd MRZ lines
+#[derive(Debug, Clone)]
+pub struct CorrectedMrz {
+    pub lines: Vec<String>,
+    pub corrections: Vec<CharacterCorrection>,
+    /// Fields whose check digit could not be satisfied by any plausible correction
+    pub unresolved: Vec<CheckedField>,
+}
+
+/// Corrects OCR confusions in MRZ fields using their check digits as an error-correcting code.
+///
+/// For every field protected by a check digit, plausible OCR substitutions are enumerated
+/// per character position, restricted to the charset the layout allows there, and the
+/// candidate with the fewest substitutions whose recomputed check digit matches is kept.
+/// Ties are broken by how well each substitution fits its neighbouring characters and then
+/// by the composite check digit; a field that remains ambiguous is left as read.
+///
+/// A single mod-10 check digit cannot tell a misread from a wrong value, so the search is kept
+/// narrow: only letter/digit look-alikes are tried (never one letter or digit for another), numeric
+/// fields accept at most one substitution, and check digits are always taken as read.
+pub struct MrzCorrector;
+
+/// A field with several equally plausible corrections
+struct Ambiguity {
+    field: CheckedField,
+    slots: Vec<Slot>,
+    candidates: Vec<Vec<char>>,
+}
+
+/// A character position inside the MRZ lines and the charset allowed there
+#[derive(Clone, Copy)]
+struct Slot {
+    line: usize,
+    position: usize,
+    charset: MrzCharset,
+}
+
+impl MrzCorrector {
+    /// Maximum number of substitutions of characters that are valid for their position
+    const MAX_SUBSTITUTIONS: usize = 2;
+    /// Maximum number of substitutions in numeric fields (dates), where any digit is plausible
+    const MAX_NUMERIC_SUBSTITUTIONS: usize = 1;
+    /// Maximum number of tied candidate combinations checked against the composite
+    const MAX_COMPOSITE_COMBINATIONS: usize = 256;
+
+    /// Correct the MRZ lines of a document format
+    pub fn correct(lines: &[&str], format: &DocumentFormat) -> CorrectedMrz {
+        Self::correct_with_ambiguities(lines, format).0
+    }
+
+    /// Plausible readings of the MRZ lines: the correction returned by `correct` first, then one
+    /// reading per candidate of every field left ambiguous, then the lines as read
+    pub fn alternatives(lines: &[&str], format: &DocumentFormat) -> Vec<CorrectedMrz> {
+        let (primary, ambiguous) = Self::correct_with_ambiguities(lines, format);
+        let original: Vec<Vec<char>> = lines.iter().map(|l| l.chars().collect()).collect();
+        let working: Vec<Vec<char>> = primary.lines.iter().map(|l| l.chars().collect()).collect();
+        let (composite_rules, field_rules): (Vec<_>, Vec<_>) = MrzLayout::check_digit_rules(format)
+            .into_iter()
+            .partition(|rule| rule.field == CheckedField::Composite);
+
+        let mut readings = vec![primary.clone()];
+        let mut add = |lines: Vec<Vec<char>>, resolved: Option<CheckedField>| {
+            let mut unresolved: Vec<CheckedField> = primary
+                .unresolved
+                .iter()
+                .copied()
+                .filter(|field| Some(*field) != resolved && *field != CheckedField::Composite)
+                .collect();
+            if resolved.is_none() {
+                // The lines as read: every failing check digit is unresolved
+                unresolved = field_rules.iter().filter(|r| !Self::satisfies(&lines, r)).map(|r| r.field).collect();
+            }
+            if composite_rules.first().is_some_and(|rule| !Self::satisfies(&lines, rule)) {
+                unresolved.push(CheckedField::Composite);
+            }
+            let reading = CorrectedMrz {
+                lines: lines.iter().map(|l| l.iter().collect()).collect(),
+                corrections: Self::diff(&original, &lines, &field_rules, &composite_rules),
+                unresolved,
+            };
+            if !readings.iter().any(|r| r.lines == reading.lines) {
+                readings.push(reading);
+            }
+        };
+
+        for ambiguity in &ambiguous {
+            for candidate in &ambiguity.candidates {
+                let mut trial = working.clone();
+                Self::apply(&mut trial, &ambiguity.slots, candidate);
+                add(trial, Some(ambiguity.field));
+            }
+        }
+        add(original.clone(), None);
+        readings
+    }
+
+    /// Correct the lines, also returning the fields left ambiguous with their candidates
+    fn correct_with_ambiguities(lines: &[&str], format: &DocumentFormat) -> (CorrectedMrz, Vec<Ambiguity>) {
+        let original: Vec<Vec<char>> = lines.iter().map(|l| l.chars().collect()).collect();
+        let mut working = original.clone();
+        let mut unresolved = Vec::new();
+        let mut ambiguous: Vec<Ambiguity> = Vec::new();
+
+        let rules = MrzLayout::check_digit_rules(format);
+        let (composite_rules, field_rules): (Vec<_>, Vec<_>) = rules
+            .into_iter()
+            .partition(|rule| rule.field == CheckedField::Composite);
+
+        for rule in &field_rules {
+            let slots = match Self::slots(&working, rule) {
+                Some(slots) => slots,
+                None => {
+                    unresolved.push(rule.field);
+                    continue;
+                }
+            };
+
+            let budget = if rule.charset == MrzCharset::Numeric {
+                Self::MAX_NUMERIC_SUBSTITUTIONS
+            } else {
+                Self::MAX_SUBSTITUTIONS
+            };
+            let candidates = Self::candidates(&working, &slots, budget);
+            match candidates.len() {
+                0 => unresolved.push(rule.field),
+                1 => Self::apply(&mut working, &slots, &candidates[0]),
+                _ => ambiguous.push(Ambiguity { field: rule.field, slots, candidates }),
+            }
+        }
+
+        if let Some(composite) = composite_rules.first() {
+            // Let the composite check digit decide between tied candidates
+            if !ambiguous.is_empty() {
+                let choices = Self::resolve_with_composite(&working, composite, &ambiguous);
+                let mut remaining = Vec::new();
+                for (ambiguity, choice) in ambiguous.into_iter().zip(choices) {
+                    match choice {
+                        Some(index) => Self::apply(&mut working, &ambiguity.slots, &ambiguity.candidates[index]),
+                        None => remaining.push(ambiguity),
+                    }
+                }
+                ambiguous = remaining;
+            }
+
+            if !Self::satisfies(&working, composite) {
+                unresolved.push(CheckedField::Composite);
+            }
+        }
+        unresolved.extend(
+            ambiguous
+                .iter()
+                .map(|ambiguity| ambiguity.field)
+                .filter(|field| *field != CheckedField::Composite),
+        );
+
+        let corrections = Self::diff(&original, &working, &field_rules, &composite_rules);
+
+        let corrected = CorrectedMrz {
+            lines: working.iter().map(|l| l.iter().collect()).collect(),
+            corrections,
+            unresolved,
+        };
+        (corrected, ambiguous)
+    }
+
+    /// Characters commonly confused with each other by OCR engines on OCR-B text.
+    ///
+    /// Only letters and digits are paired: a digit read as another digit, or a letter read as
+    /// another letter, is indistinguishable from a wrong value once a substitution may be traded
+    /// against the check digit, so trying them would invent a different, valid-looking field.
+    pub fn confusions(c: char) -> &'static [char] {
+        match c {
+            '0' => &['O', 'Q', 'D', 'U', 'C'],
+            'O' | 'Q' | 'D' | 'U' | 'C' => &['0'],
+            '1' => &['I', 'L', 'T'],
+            'I' | 'L' => &['1'],
+            'T' => &['7', '1'],
+            '2' => &['Z'],
+            'Z' => &['2', '7'],
+            '3' => &['E', 'B'],
+            'E' => &['3'],
+            '4' => &['A'],
+            'A' => &['4'],
+            '5' => &['S'],
+            'S' => &['5', '8'],
+            '6' => &['G', 'B'],
+            'G' => &['6'],
+            '7' => &['T', 'Z'],
+            '8' => &['B', 'S'],
+            'B' => &['8', '3', '6'],
+            _ => &[],
+        }
+    }
+
+    /// Positions covered by a rule, with the check digit last.
+    ///
+    /// Returns None for document numbers that overflow into the optional data, whose
+    /// check digit position depends on the characters being corrected.
+    fn slots(lines: &[Vec<char>], rule: &CheckDigitRule) -> Option<Vec<Slot>> {
+        let check = Slot {
+            line: rule.check.line,
+            position: rule.check.start,
+            charset: MrzCharset::Numeric,
+        };
+        let check_char = *lines.get(check.line)?.get(check.position)?;
+        if rule.overflow.is_some() && check_char == '<' {
+            return None;
+        }
+
+        let mut slots = Vec::new();
+        for span in &rule.spans {
+            if lines.get(span.line)?.len() < span.end {
+                return None;
+            }
+            slots.extend((span.start..span.end).map(|position| Slot {
+                line: span.line,
+                position,
+                charset: rule.charset,
+            }));
+        }
+        slots.push(check);
+        Some(slots)
+    }
+
+    /// Distinct candidates with the fewest substitutions (at most `max_substitutions` of
+    /// characters valid for their position) that satisfy the check digit, which is the last
+    /// slot and never substituted
+    fn candidates(lines: &[Vec<char>], slots: &[Slot], max_substitutions: usize) -> Vec<Vec<char>> {
+        let original: Vec<char> = slots.iter().map(|s| lines[s.line][s.position]).collect();
+        let data = slots.len() - 1;
+        let options: Vec<Vec<char>> = slots
+            .iter()
+            .zip(&original)
+            .enumerate()
+            .map(|(i, (slot, c))| {
+                if i == data {
+                    return Vec::new();
+                }
+                Self::confusions(*c)
+                    .iter()
+                    .copied()
+                    .filter(|alt| slot.charset.allows(*alt))
+                    .collect()
+            })
+            .collect();
+        let forced: Vec<bool> = slots
+            .iter()
+            .zip(&original)
+            .enumerate()
+            .map(|(i, (slot, c))| i != data && !slot.charset.allows(*c))
+            .collect();
+
+        for budget in 0..=max_substitutions {
+            let mut found = Vec::new();
+            let mut current = original.clone();
+            Self::enumerate(0, budget, &mut current, &original, &options, &forced, &mut found);
+            if !found.is_empty() {
+                return Self::most_plausible(&original, slots, found);
+            }
+        }
+        Vec::new()
+    }
+
+    /// Keep the candidates whose substitutions best fit their neighbours.
+    ///
+    /// A letter between two digits (or a digit between two letters) of the same field is
+    /// the usual OCR error, so substitutions that remove such an outlier score higher,
+    /// and more so when both neighbours agree than at the edge of a field.
+    fn most_plausible(original: &[char], slots: &[Slot], candidates: Vec<Vec<char>>) -> Vec<Vec<char>> {
+        if candidates.len() < 2 {
+            return candidates;
+        }
+
+        let is_digit = |c: char| c.is_ascii_digit();
+        let score = |candidate: &Vec<char>| -> usize {
+            (0..original.len())
+                .filter(|&i| candidate[i] != original[i])
+                .map(|i| {
+                    let neighbour = |j: usize| {
+                        let adjacent = slots[j].line == slots[i].line
+                            && slots[j].position.abs_diff(slots[i].position) == 1
+                            && slots[j].charset == slots[i].charset;
+                        if adjacent {
+                            Some(original[j])
+                        } else {
+                            None
+                        }
+                    };
+                    let left = i.checked_sub(1).and_then(neighbour);
+                    let right = (i + 1 < original.len()).then(|| neighbour(i + 1)).flatten();
+                    let neighbours: Vec<char> = left.into_iter().chain(right).filter(|c| *c != '<').collect();
+                    let removes_outlier = neighbours.iter().all(|n| is_digit(*n) == is_digit(candidate[i]))
+                        && is_digit(original[i]) != is_digit(candidate[i]);
+                    if removes_outlier {
+                        neighbours.len()
+                    } else {
+                        0
+                    }
+                })
+                .sum()
+        };
+
+        let best = candidates.iter().map(score).max().unwrap_or(0);
+        candidates.into_iter().filter(|c| score(c) == best).collect()
+    }
+
+    /// Depth-first enumeration of candidates using exactly `budget` optional substitutions
+    fn enumerate(
+        index: usize,
+        budget: usize,
+        current: &mut Vec<char>,
+        original: &[char],
+        options: &[Vec<char>],
+        forced: &[bool],
+        found: &mut Vec<Vec<char>>,
+    ) {
+        if index == current.len() {
+            let (data, check) = current.split_at(current.len() - 1);
+            if budget == 0 && CheckDigitCalculator::verify(&data.iter().collect::<String>(), check[0]) {
+                found.push(current.clone());
+            }
+            return;
+        }
+
+        if !forced[index] {
+            Self::enumerate(index + 1, budget, current, original, options, forced, found);
+        }
+        if forced[index] || budget > 0 {
+            let remaining = if forced[index] { budget } else { budget - 1 };
+            for alt in &options[index] {
+                current[index] = *alt;
+                Self::enumerate(index + 1, remaining, current, original, options, forced, found);
+            }
+            current[index] = original[index];
+        }
+    }
+
+    /// Index of the candidate to use for each ambiguous field, where every combination that
+    /// satisfies the composite check digit agrees on it
+    fn resolve_with_composite(
+        lines: &[Vec<char>],
+        composite: &CheckDigitRule,
+        ambiguous: &[Ambiguity],
+    ) -> Vec<Option<usize>> {
+        let combinations: usize = ambiguous.iter().map(|a| a.candidates.len()).product();
+        if combinations == 0 || combinations > Self::MAX_COMPOSITE_COMBINATIONS {
+            return vec![None; ambiguous.len()];
+        }
+
+        let mut matches: Vec<Vec<usize>> = Vec::new();
+        for n in 0..combinations {
+            let mut rest = n;
+            let choice: Vec<usize> = ambiguous
+                .iter()
+                .map(|ambiguity| {
+                    let index = rest % ambiguity.candidates.len();
+                    rest /= ambiguity.candidates.len();
+                    index
+                })
+                .collect();
+
+            let mut trial = lines.to_vec();
+            for (ambiguity, index) in ambiguous.iter().zip(&choice) {
+                Self::apply(&mut trial, &ambiguity.slots, &ambiguity.candidates[*index]);
+            }
+            if Self::satisfies(&trial, composite) {
+                matches.push(choice);
+            }
+        }
+
+        (0..ambiguous.len())
+            .map(|i| {
+                let first = matches.first()?[i];
+                matches.iter().all(|choice| choice[i] == first).then_some(first)
+            })
+            .collect()
+    }
+
+    fn satisfies(lines: &[Vec<char>], rule: &CheckDigitRule) -> bool {
+        let lines: Vec<String> = lines.iter().map(|l| l.iter().collect()).collect();
+        let line_refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+        MrzLayout::check_digit_input(&line_refs, rule)
+            .is_some_and(|(data, check)| CheckDigitCalculator::verify(&data, check))
+    }
+
+    fn apply(lines: &mut [Vec<char>], slots: &[Slot], candidate: &[char]) {
+        for (slot, c) in slots.iter().zip(candidate) {
+            lines[slot.line][slot.position] = *c;
+        }
+    }
+
+    /// Characters that differ between the original and corrected lines, labelled by field
+    fn diff(
+        original: &[Vec<char>],
+        corrected: &[Vec<char>],
+        field_rules: &[CheckDigitRule],
+        composite_rules: &[CheckDigitRule],
+    ) -> Vec<CharacterCorrection> {
+        let mut corrections = Vec::new();
+        for (line, (before, after)) in original.iter().zip(corrected).enumerate() {
+            for (position, (o, c)) in before.iter().zip(after).enumerate() {
+                if o == c {
+                    continue;
+                }
+                let covers = |rule: &&CheckDigitRule| {
+                    rule.spans
+                        .iter()
+                        .chain(std::iter::once(&rule.check))
+                        .any(|s| s.line == line && (s.start..s.end).contains(&position))
+                };
+                let field = field_rules
+                    .iter()
+                    .find(covers)
+                    .or_else(|| composite_rules.iter().find(covers))
+                    .map_or("Unknown", |rule| rule.field.name());
+                corrections.push(CharacterCorrection {
+                    field: field.to_string(),
+                    line,
+                    position,
+                    original: *o,
+                    corrected: *c,
+                });
+            }
+        }
+        corrections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE1: &str = "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<";
+    const LINE2: &str = "L898902C36UTO7408122F1204159ZE184226B<<<<<10";
+
+    fn correct(line2: &str) -> CorrectedMrz {
+        MrzCorrector::correct(&[LINE1, line2], &DocumentFormat::TD3)
+    }
+
+    #[test]
+    fn corrects_fields_to_satisfy_their_check_digits() {
+        // 8 read as B between two digits of the document number
+        let result = correct("L89B902C36UTO7408122F1204159ZE184226B<<<<<10");
+        assert_eq!(result.lines[1], LINE2);
+        assert!(result.unresolved.is_empty(), "{:?}", result.unresolved);
+        assert_eq!(result.corrections.len(), 1);
+        assert_eq!(result.corrections[0].field, "Document number");
+        assert_eq!((result.corrections[0].original, result.corrections[0].corrected), ('B', '8'));
+
+        // A letter in a date can only be a misread digit
+        let result = correct("L898902C36UTO74O8122F1204159ZE184226B<<<<<10");
+        assert_eq!(result.lines[1], LINE2);
+        assert_eq!(result.corrections[0].field, "Date of birth");
+    }
+
+    #[test]
+    fn flags_ambiguous_fields_instead_of_guessing() {
+        // The last character of the document number (3) read as B: B -> 3 is one of several
+        // corrections that satisfy the check digit, and the composite cannot tell them apart
+        let read = "L898902CB6UTO7408122F1204159ZE184226B<<<<<10";
+        let result = correct(read);
+        assert_eq!(result.lines[1], read);
+        assert!(result.unresolved.contains(&CheckedField::DocumentNumber));
+        assert!(result.corrections.is_empty());
+
+        // The candidates are still offered as alternative readings
+        let alternatives = MrzCorrector::alternatives(&[LINE1, read], &DocumentFormat::TD3);
+        assert_eq!(alternatives[0].lines[1], read);
+        assert!(alternatives.len() > 2);
+        assert!(alternatives.iter().any(|reading| reading.lines[1] == LINE2));
+    }
+
+    #[test]
+    fn leaves_wrong_digits_in_numeric_fields_unresolved() {
+        // 740812 read as 740813 is a wrong value, not a look-alike
+        let read = "L898902C36UTO7408132F1204159ZE184226B<<<<<10";
+        let result = correct(read);
+        assert_eq!(result.lines[1], read);
+        assert!(result.unresolved.contains(&CheckedField::DateOfBirth));
+    }
+
+    #[test]
+    fn never_swaps_a_letter_for_another_letter() {
+        for c in ('A'..='Z').chain('0'..='9').chain(std::iter::once('<')) {
+            for alternative in MrzCorrector::confusions(c) {
+                assert!(
+                    c.is_ascii_digit() != alternative.is_ascii_digit(),
+                    "{} is confused with {}",
+                    c,
+                    alternative
+                );
+            }
+        }
+
+        // L read as M is not undone, whatever else satisfies the check digit
+        let result = correct("M898902C36UTO7408122F1204159ZE184226B<<<<<10");
+        assert!(result.lines[1].starts_with('M'));
+        for correction in &result.corrections {
+            assert!(correction.original.is_ascii_digit() != correction.corrected.is_ascii_digit());
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use crate::models::{CharacterCorrection, DocumentFormat};
+use crate::mrz::{MrzCorrector, MrzLayout};
+
+/// What a three-letter MRZ code designates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeKind {
+    /// ISO 3166-1 alpha-3 state or territory
+    State,
+    /// International organization issuing travel documents
+    Organization,
+    /// ICAO code for a nationality status (British nationals, stateless persons, refugees...)
+    Special,
+    /// Code withdrawn from ISO 3166-1, still found on older documents
+    Historical,
+}
+
+/// A code accepted in the issuing state and nationality fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountryCode {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub kind: CodeKind,
+}
+
+/// Registry of the codes ICAO Doc 9303 Part 3 allows in the issuing state and nationality
+/// fields: ISO 3166-1 alpha-3, ICAO specific codes and historical codes
+pub struct CountryRegistry;
+
+impl CountryRegistry {
+    /// Maximum number of OCR substitutions when snapping a code to the registry
+    const MAX_SNAP_SUBSTITUTIONS: usize = 2;
+
+    /// Look up a code, with or without trailing fillers (e.g. "D<<" or "D")
+    pub fn lookup(code: &str) -> Option<&'static CountryCode> {
+        let code = code.trim_end_matches('<');
+        CODES.iter().find(|entry| entry.code == code)
+    }
+
+    pub fn is_valid(code: &str) -> bool {
+        Self::lookup(code).is_some()
+    }
+
+    pub fn name(code: &str) -> Option<&'static str> {
+        Self::lookup(code).map(|entry| entry.name)
+    }
+
+    /// The registry code an OCR'd code was most likely read from (e.g. "UT0" -> "UTO").
+    ///
+    /// Codes reachable with the fewest letter/digit confusions are considered; None is returned
+    /// when nothing is reachable or several codes are equally close.
+    pub fn snap(code: &str) -> Option<&'static CountryCode> {
+        if let Some(entry) = Self::lookup(code) {
+            return Some(entry);
+        }
+
+        // Work on the padded three-character field so fillers can be confused too
+        let field: Vec<char> = format!("{:<<3}", code).chars().take(3).collect();
+        let mut variants = vec![field];
+        for _ in 0..Self::MAX_SNAP_SUBSTITUTIONS {
+            let mut next = Vec::new();
+            for variant in &variants {
+                for (position, c) in variant.iter().enumerate() {
+                    for alternative in MrzCorrector::confusions(*c) {
+                        let mut candidate = variant.clone();
+                        candidate[position] = *alternative;
+                        if !next.contains(&candidate) {
+                            next.push(candidate);
+                        }
+                    }
+                }
+            }
+
+            let mut matches: Vec<&'static CountryCode> = Vec::new();
+            for candidate in &next {
+                let candidate: String = candidate.iter().collect();
+                if let Some(entry) = Self::lookup(&candidate) {
+                    if !matches.contains(&entry) {
+                        matches.push(entry);
+                    }
+                }
+            }
+            match matches.len() {
+                0 => variants = next,
+                1 => return Some(matches[0]),
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Snap the issuing state and nationality fields of MRZ lines to registry codes,
+    /// returning the corrected lines and every character changed.
+    ///
+    /// No check digit protects these codes, so only a code that cannot be right as read is
+    /// snapped: one holding a digit, or a filler inside it. An all-letter code missing from
+    /// the registry may be genuine and is left for validation to report.
+    pub fn snap_mrz(lines: &[&str], format: &DocumentFormat) -> (Vec<String>, Vec<CharacterCorrection>) {
+        let mut corrected: Vec<Vec<char>> = lines.iter().map(|l| l.chars().collect()).collect();
+        let mut corrections = Vec::new();
+
+        for (field, span) in MrzLayout::code_spans(format) {
+            let code = match span.extract(lines) {
+                Some(code) if !Self::is_valid(code) && Self::is_misread(code) => code,
+                _ => continue,
+            };
+            let entry = match Self::snap(code) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let snapped = format!("{:<<3}", entry.code);
+            for (offset, (original, replacement)) in code.chars().zip(snapped.chars()).enumerate() {
+                if original != replacement {
+                    corrected[span.line][span.start + offset] = replacement;
+                    corrections.push(CharacterCorrection {
+                        field: field.to_string(),
+                        line: span.line,
+                        position: span.start + offset,
+                        original,
+                        corrected: replacement,
+                    });
+                }
+            }
+        }
+
+        (corrected.iter().map(|l| l.iter().collect()).collect(), corrections)
+    }
+
+    /// Whether a code field holds characters no code has: a digit, or a filler before a letter
+    fn is_misread(code: &str) -> bool {
+        code.chars().any(|c| c.is_ascii_digit()) || code.trim_end_matches('<').contains('<')
+    }
+}
+
+const fn state(code: &'static str, name: &'static str) -> CountryCode {
+    CountryCode { code, name, kind: CodeKind::State }
+}
+
+const fn organization(code: &'static str, name: &'static str) -> CountryCode {
+    CountryCode { code, name, kind: CodeKind::Organization }
+}
+
+const fn special(code: &'static str, name: &'static str) -> CountryCode {
+    CountryCode { code, name, kind: CodeKind::Special }
+}
+
+const fn historical(code: &'static str, name: &'static str) -> CountryCode {
+    CountryCode { code, name, kind: CodeKind::Historical }
+}
+
+static CODES: &[CountryCode] = &[
+    // ISO 3166-1 alpha-3
+    state("AFG", "Afghanistan"),
+    state("ALA", "Åland Islands"),
+    state("ALB", "Albania"),
+    state("DZA", "Algeria"),
+    state("ASM", "American Samoa"),
+    state("AND", "Andorra"),
+    state("AGO", "Angola"),
+    state("AIA", "Anguilla"),
+    state("ATA", "Antarctica"),
+    state("ATG", "Antigua and Barbuda"),
+    state("ARG", "Argentina"),
+    state("ARM", "Armenia"),
+    state("ABW", "Aruba"),
+    state("AUS", "Australia"),
+    state("AUT", "Austria"),
+    state("AZE", "Azerbaijan"),
+    state("BHS", "Bahamas"),
+    state("BHR", "Bahrain"),
+    state("BGD", "Bangladesh"),
+    state("BRB", "Barbados"),
+    state("BLR", "Belarus"),
+    state("BEL", "Belgium"),
+    state("BLZ", "Belize"),
+    state("BEN", "Benin"),
+    state("BMU", "Bermuda"),
+    state("BTN", "Bhutan"),
+    state("BOL", "Bolivia"),
+    state("BES", "Bonaire, Sint Eustatius and Saba"),
+    state("BIH", "Bosnia and Herzegovina"),
+    state("BWA", "Botswana"),
+    state("BVT", "Bouvet Island"),
+    state("BRA", "Brazil"),
+    state("IOT", "British Indian Ocean Territory"),
+    state("BRN", "Brunei Darussalam"),
+    state("BGR", "Bulgaria"),
+    state("BFA", "Burkina Faso"),
+    state("BDI", "Burundi"),
+    state("CPV", "Cabo Verde"),
+    state("KHM", "Cambodia"),
+    state("CMR", "Cameroon"),
+    state("CAN", "Canada"),
+    state("CYM", "Cayman Islands"),
+    state("CAF", "Central African Republic"),
+    state("TCD", "Chad"),
+    state("CHL", "Chile"),
+    state("CHN", "China"),
+    state("CXR", "Christmas Island"),
+    state("CCK", "Cocos (Keeling) Islands"),
+    state("COL", "Colombia"),
+    state("COM", "Comoros"),
+    state("COG", "Congo"),
+    state("COD", "Congo, Democratic Republic of the"),
+    state("COK", "Cook Islands"),
+    state("CRI", "Costa Rica"),
+    state("CIV", "Côte d'Ivoire"),
+    state("HRV", "Croatia"),
+    state("CUB", "Cuba"),
+    state("CUW", "Curaçao"),
+    state("CYP", "Cyprus"),
+    state("CZE", "Czechia"),
+    state("DNK", "Denmark"),
+    state("DJI", "Djibouti"),
+    state("DMA", "Dominica"),
+    state("DOM", "Dominican Republic"),
+    state("ECU", "Ecuador"),
+    state("EGY", "Egypt"),
+    state("SLV", "El Salvador"),
+    state("GNQ", "Equatorial Guinea"),
+    state("ERI", "Eritrea"),
+    state("EST", "Estonia"),
+    state("SWZ", "Eswatini"),
+    state("ETH", "Ethiopia"),
+    state("FLK", "Falkland Islands (Malvinas)"),
+    state("FRO", "Faroe Islands"),
+    state("FJI", "Fiji"),
+    state("FIN", "Finland"),
+    state("FRA", "France"),
+    state("GUF", "French Guiana"),
+    state("PYF", "French Polynesia"),
+    state("ATF", "French Southern Territories"),
+    state("GAB", "Gabon"),
+    state("GMB", "Gambia"),
+    state("GEO", "Georgia"),
+    state("DEU", "Germany"),
+    state("GHA", "Ghana"),
+    state("GIB", "Gibraltar"),
+    state("GRC", "Greece"),
+    state("GRL", "Greenland"),
+    state("GRD", "Grenada"),
+    state("GLP", "Guadeloupe"),
+    state("GUM", "Guam"),
+    state("GTM", "Guatemala"),
+    state("GGY", "Guernsey"),
+    state("GIN", "Guinea"),
+    state("GNB", "Guinea-Bissau"),
+    state("GUY", "Guyana"),
+    state("HTI", "Haiti"),
+    state("HMD", "Heard Island and McDonald Islands"),
+    state("VAT", "Holy See"),
+    state("HND", "Honduras"),
+    state("HKG", "Hong Kong"),
+    state("HUN", "Hungary"),
+    state("ISL", "Iceland"),
+    state("IND", "India"),
+    state("IDN", "Indonesia"),
+    state("IRN", "Iran"),
+    state("IRQ", "Iraq"),
+    state("IRL", "Ireland"),
+    state("IMN", "Isle of Man"),
+    state("ISR", "Israel"),
+    state("ITA", "Italy"),
+    state("JAM", "Jamaica"),
+    state("JPN", "Japan"),
+    state("JEY", "Jersey"),
+    state("JOR", "Jordan"),
+    state("KAZ", "Kazakhstan"),
+    state("KEN", "Kenya"),
+    state("KIR", "Kiribati"),
+    state("PRK", "Korea, Democratic People's Republic of"),
+    state("KOR", "Korea, Republic of"),
+    state("KWT", "Kuwait"),
+    state("KGZ", "Kyrgyzstan"),
+    state("LAO", "Lao People's Democratic Republic"),
+    state("LVA", "Latvia"),
+    state("LBN", "Lebanon"),
+    state("LSO", "Lesotho"),
+    state("LBR", "Liberia"),
+    state("LBY", "Libya"),
+    state("LIE", "Liechtenstein"),
+    state("LTU", "Lithuania"),
+    state("LUX", "Luxembourg"),
+    state("MAC", "Macao"),
+    state("MDG", "Madagascar"),
+    state("MWI", "Malawi"),
+    state("MYS", "Malaysia"),
+    state("MDV", "Maldives"),
+    state("MLI", "Mali"),
+    state("MLT", "Malta"),
+    state("MHL", "Marshall Islands"),
+    state("MTQ", "Martinique"),
+    state("MRT", "Mauritania"),
+    state("MUS", "Mauritius"),
+    state("MYT", "Mayotte"),
+    state("MEX", "Mexico"),
+    state("FSM", "Micronesia, Federated States of"),
+    state("MDA", "Moldova"),
+    state("MCO", "Monaco"),
+    state("MNG", "Mongolia"),
+    state("MNE", "Montenegro"),
+    state("MSR", "Montserrat"),
+    state("MAR", "Morocco"),
+    state("MOZ", "Mozambique"),
+    state("MMR", "Myanmar"),
+    state("NAM", "Namibia"),
+    state("NRU", "Nauru"),
+    state("NPL", "Nepal"),
+    state("NLD", "Netherlands"),
+    state("NCL", "New Caledonia"),
+    state("NZL", "New Zealand"),
+    state("NIC", "Nicaragua"),
+    state("NER", "Niger"),
+    state("NGA", "Nigeria"),
+    state("NIU", "Niue"),
+    state("NFK", "Norfolk Island"),
+    state("MKD", "North Macedonia"),
+    state("MNP", "Northern Mariana Islands"),
+    state("NOR", "Norway"),
+    state("OMN", "Oman"),
+    state("PAK", "Pakistan"),
+    state("PLW", "Palau"),
+    state("PSE", "Palestine, State of"),
+    state("PAN", "Panama"),
+    state("PNG", "Papua New Guinea"),
+    state("PRY", "Paraguay"),
+    state("PER", "Peru"),
+    state("PHL", "Philippines"),
+    state("PCN", "Pitcairn"),
+    state("POL", "Poland"),
+    state("PRT", "Portugal"),
+    state("PRI", "Puerto Rico"),
+    state("QAT", "Qatar"),
+    state("REU", "Réunion"),
+    state("ROU", "Romania"),
+    state("RUS", "Russian Federation"),
+    state("RWA", "Rwanda"),
+    state("BLM", "Saint Barthélemy"),
+    state("SHN", "Saint Helena, Ascension and Tristan da Cunha"),
+    state("KNA", "Saint Kitts and Nevis"),
+    state("LCA", "Saint Lucia"),
+    state("MAF", "Saint Martin (French part)"),
+    state("SPM", "Saint Pierre and Miquelon"),
+    state("VCT", "Saint Vincent and the Grenadines"),
+    state("WSM", "Samoa"),
+    state("SMR", "San Marino"),
+    state("STP", "Sao Tome and Principe"),
+    state("SAU", "Saudi Arabia"),
+    state("SEN", "Senegal"),
+    state("SRB", "Serbia"),
+    state("SYC", "Seychelles"),
+    state("SLE", "Sierra Leone"),
+    state("SGP", "Singapore"),
+    state("SXM", "Sint Maarten (Dutch part)"),
+    state("SVK", "Slovakia"),
+    state("SVN", "Slovenia"),
+    state("SLB", "Solomon Islands"),
+    state("SOM", "Somalia"),
+    state("ZAF", "South Africa"),
+    state("SGS", "South Georgia and the South Sandwich Islands"),
+    state("SSD", "South Sudan"),
+    state("ESP", "Spain"),
+    state("LKA", "Sri Lanka"),
+    state("SDN", "Sudan"),
+    state("SUR", "Suriname"),
+    state("SJM", "Svalbard and Jan Mayen"),
+    state("SWE", "Sweden"),
+    state("CHE", "Switzerland"),
+    state("SYR", "Syrian Arab Republic"),
+    state("TWN", "Taiwan"),
+    state("TJK", "Tajikistan"),
+    state("TZA", "Tanzania, United Republic of"),
+    state("THA", "Thailand"),
+    state("TLS", "Timor-Leste"),
+    state("TGO", "Togo"),
+    state("TKL", "Tokelau"),
+    state("TON", "Tonga"),
+    state("TTO", "Trinidad and Tobago"),
+    state("TUN", "Tunisia"),
+    state("TUR", "Türkiye"),
+    state("TKM", "Turkmenistan"),
+    state("TCA", "Turks and Caicos Islands"),
+    state("TUV", "Tuvalu"),
+    state("UGA", "Uganda"),
+    state("UKR", "Ukraine"),
+    state("ARE", "United Arab Emirates"),
+    state("GBR", "United Kingdom of Great Britain and Northern Ireland"),
+    state("USA", "United States of America"),
+    state("UMI", "United States Minor Outlying Islands"),
+    state("URY", "Uruguay"),
+    state("UZB", "Uzbekistan"),
+    state("VUT", "Vanuatu"),
+    state("VEN", "Venezuela"),
+    state("VNM", "Viet Nam"),
+    state("VGB", "Virgin Islands (British)"),
+    state("VIR", "Virgin Islands (U.S.)"),
+    state("WLF", "Wallis and Futuna"),
+    state("ESH", "Western Sahara"),
+    state("YEM", "Yemen"),
+    state("ZMB", "Zambia"),
+    state("ZWE", "Zimbabwe"),
+    // ICAO Doc 9303 Part 3 codes
+    special("D", "Germany"),
+    special("GBD", "British Overseas Territories Citizen"),
+    special("GBN", "British National (Overseas)"),
+    special("GBO", "British Overseas Citizen"),
+    special("GBP", "British Protected Person"),
+    special("GBS", "British Subject"),
+    special("RKS", "Kosovo"),
+    special("XKX", "Kosovo"),
+    special("UTO", "Utopia (ICAO specimen)"),
+    special("XXA", "Stateless person"),
+    special("XXB", "Refugee (1951 Convention)"),
+    special("XXC", "Refugee (other)"),
+    special("XXX", "Unspecified nationality"),
+    organization("UNO", "United Nations Organization"),
+    organization("UNA", "Specialized agency of the United Nations"),
+    organization("UNK", "United Nations Interim Administration Mission in Kosovo"),
+    organization("EUE", "European Union"),
+    organization("XBA", "African Development Bank"),
+    organization("XIM", "African Export-Import Bank"),
+    organization("XCC", "Caribbean Community"),
+    organization("XCE", "Council of Europe"),
+    organization("XCO", "Common Market for Eastern and Southern Africa"),
+    organization("XDC", "Southern African Development Community"),
+    organization("XEC", "Economic Community of West African States"),
+    organization("XES", "Organisation of Eastern Caribbean States"),
+    organization("XMP", "Parliamentary Assembly of the Mediterranean"),
+    organization("XOM", "Sovereign Military Order of Malta"),
+    organization("XPO", "International Criminal Police Organization (INTERPOL)"),
+    // Withdrawn codes
+    historical("AFI", "French Afars and Issas"),
+    historical("ANT", "Netherlands Antilles"),
+    historical("ATB", "British Antarctic Territory"),
+    historical("ATN", "Dronning Maud Land"),
+    historical("BUR", "Burma"),
+    historical("BYS", "Byelorussian SSR"),
+    historical("CSK", "Czechoslovakia"),
+    historical("CTE", "Canton and Enderbury Islands"),
+    historical("DDR", "German Democratic Republic"),
+    historical("DHY", "Dahomey"),
+    historical("FXX", "France, Metropolitan"),
+    historical("GEL", "Gilbert and Ellice Islands"),
+    historical("HVO", "Upper Volta"),
+    historical("JTN", "Johnston Island"),
+    historical("MID", "Midway Islands"),
+    historical("NHB", "New Hebrides"),
+    historical("NTZ", "Neutral Zone"),
+    historical("PCI", "Pacific Islands (Trust Territory)"),
+    historical("PCZ", "Panama Canal Zone"),
+    historical("PUS", "US Miscellaneous Pacific Islands"),
+    historical("RHO", "Southern Rhodesia"),
+    historical("ROM", "Romania"),
+    historical("SCG", "Serbia and Montenegro"),
+    historical("SKM", "Sikkim"),
+    historical("SUN", "USSR"),
+    historical("TMP", "East Timor"),
+    historical("VDR", "Viet-Nam, Democratic Republic of"),
+    historical("WAK", "Wake Island"),
+    historical("YMD", "Yemen, Democratic"),
+    historical("YUG", "Yugoslavia"),
+    historical("ZAR", "Zaire"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE1: &str = "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<";
+    const LINE2: &str = "L898902C36UTO7408122F1204159ZE184226B<<<<<10";
+
+    #[test]
+    fn snaps_codes_with_misread_digits() {
+        assert_eq!(CountryRegistry::snap("UT0").map(|entry| entry.code), Some("UTO"));
+        assert_eq!(CountryRegistry::snap("D<<").map(|entry| entry.code), Some("D"));
+
+        let line2 = LINE2.replacen("UTO", "UT0", 1);
+        let (lines, corrections) = CountryRegistry::snap_mrz(&[LINE1, &line2], &DocumentFormat::TD3);
+        assert_eq!(lines[1], LINE2);
+        assert_eq!(corrections.len(), 1);
+        assert_eq!(corrections[0].field, "Nationality");
+        assert_eq!((corrections[0].original, corrections[0].corrected), ('0', 'O'));
+    }
+
+    #[test]
+    fn leaves_all_letter_codes_as_read() {
+        // No letter is ever swapped for another, so an unknown code is not turned into a country
+        assert!(CountryRegistry::snap("NEX").is_none());
+
+        let line1 = LINE1.replacen("UTO", "UTQ", 1);
+        let (lines, corrections) = CountryRegistry::snap_mrz(&[&line1, LINE2], &DocumentFormat::TD3);
+        assert_eq!(lines[0], line1);
+        assert!(corrections.is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+use chrono::{Datelike, Local, NaiveDate};
+
+/// Role of a date, which determines how its two-digit year is resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateKind {
+    Birth,
+    Expiry,
+    Issue,
+}
+
+/// Policy resolving the two-digit years of MRZ dates (YYMMDD) against a reference date.
+///
+/// - Birth dates are the most recent date not after the reference date
+/// - Expiry dates fall within a window of years around the reference date
+/// - Issue dates are the most recent date not after the reference date nor the expiry date
+///
+/// The reference date defaults to today and can be overridden to get reproducible results.
+#[derive(Debug, Clone)]
+pub struct DatePolicy {
+    reference_date: NaiveDate,
+    expiry_years_past: i32,
+    expiry_years_future: i32,
+}
+
+impl Default for DatePolicy {
+    fn default() -> Self {
+        Self::new(Local::now().date_naive())
+    }
+}
+
+impl DatePolicy {
+    /// Expired documents up to this many years old are still read with their original century
+    pub const DEFAULT_EXPIRY_YEARS_PAST: i32 = 80;
+    /// No document is issued with a validity longer than this many years
+    pub const DEFAULT_EXPIRY_YEARS_FUTURE: i32 = 20;
+
+    pub fn new(reference_date: NaiveDate) -> Self {
+        DatePolicy {
+            reference_date,
+            expiry_years_past: Self::DEFAULT_EXPIRY_YEARS_PAST,
+            expiry_years_future: Self::DEFAULT_EXPIRY_YEARS_FUTURE,
+        }
+    }
+
+    /// Accept expiry dates from `past` years before to `future` years after the reference date
+    pub fn with_expiry_window(mut self, past: i32, future: i32) -> Self {
+        self.expiry_years_past = past;
+        self.expiry_years_future = future;
+        self
+    }
+
+    pub fn reference_date(&self) -> NaiveDate {
+        self.reference_date
+    }
+
+    /// Resolve a two-digit year to a full year.
+    ///
+    /// Month and day only decide between centuries at the boundary (e.g. a birthday later in
+    /// the reference year), so out-of-range values are tolerated. Returns None when no century
+    /// places the date inside the policy's bounds.
+    pub fn resolve_year(&self, kind: DateKind, yy: u32, month: u32, day: u32) -> Option<i32> {
+        match kind {
+            DateKind::Birth => Some(Self::latest_not_after(yy, month, day, self.reference_date)),
+            DateKind::Issue => self.resolve_issue_year(yy, month, day, None),
+            DateKind::Expiry => {
+                let reference_year = self.reference_date.year();
+                let first = reference_year - self.expiry_years_past;
+                let last = reference_year + self.expiry_years_future;
+                (first..=last)
+                    .filter(|year| year.rem_euclid(100) == yy as i32)
+                    .min_by_key(|year| (year - reference_year).abs())
+            }
+        }
+    }
+
+    /// Resolve the year of an issue date, which precedes both the reference and expiry dates
+    pub fn resolve_issue_year(&self, yy: u32, month: u32, day: u32, expiry: Option<NaiveDate>) -> Option<i32> {
+        let bound = expiry.map_or(self.reference_date, |expiry| expiry.min(self.reference_date));
+        Some(Self::latest_not_after(yy, month, day, bound))
+    }
+
+    /// Resolve an MRZ YYMMDD date, or None if it is not a valid calendar date within the policy
+    pub fn resolve(&self, kind: DateKind, yymmdd: &str) -> Option<NaiveDate> {
+        if yymmdd.len() != 6 || !yymmdd.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let yy = yymmdd[0..2].parse().ok()?;
+        let month = yymmdd[2..4].parse().ok()?;
+        let day = yymmdd[4..6].parse().ok()?;
+        let year = self.resolve_year(kind, yy, month, day)?;
+        NaiveDate::from_ymd_opt(year, month, day)
+    }
+
+    /// Latest year ending in `yy` such that the date is not after `bound`
+    fn latest_not_after(yy: u32, month: u32, day: u32, bound: NaiveDate) -> i32 {
+        let mut year = bound.year() - (bound.year() - yy as i32).rem_euclid(100);
+        if (month, day) > (bound.month(), bound.day()) && year == bound.year() {
+            year -= 100;
+        }
+        year
+    }
+}
@@ -0,0 +1,99 @@
+use crate::models::DocumentFormat;
+
+/// A document code and the formats it is issued in, either for every issuer or for one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DocumentTypeEntry {
+    /// Issuing state or organization, None for codes every issuer may use
+    pub issuer: Option<&'static str>,
+    pub code: &'static str,
+    pub description: &'static str,
+    pub formats: &'static [DocumentFormat],
+}
+
+/// Registry of the document codes found in the first two MRZ characters.
+///
+/// ICAO Doc 9303 fixes the first letter (P, I, A, C, V) and leaves the second one to the
+/// issuer, so besides the codes in common use some issuers define their own. The common codes
+/// follow Doc 9303 and issuer practice; the issuer-specific ones are only those seen on
+/// published specimens and are far from complete. A genuine issuer code missing here is
+/// reported by `MRTDVerifier` as a format issue until its entry is added.
+pub struct DocumentTypeRegistry;
+
+impl DocumentTypeRegistry {
+    /// Look up a code for an issuer, with or without trailing fillers (e.g. "P<" or "P");
+    /// issuer-specific entries take precedence over common ones
+    pub fn lookup(issuer: &str, code: &str) -> Option<&'static DocumentTypeEntry> {
+        let issuer = issuer.trim_end_matches('<');
+        let code = code.trim_end_matches('<');
+        DOCUMENT_TYPES
+            .iter()
+            .find(|entry| entry.code == code && entry.issuer == Some(issuer))
+            .or_else(|| DOCUMENT_TYPES.iter().find(|entry| entry.code == code && entry.issuer.is_none()))
+    }
+
+    pub fn is_known(issuer: &str, code: &str) -> bool {
+        Self::lookup(issuer, code).is_some()
+    }
+
+    /// Whether the code is known for the issuer and issued in the given format
+    pub fn is_valid_for_format(issuer: &str, code: &str, format: &DocumentFormat) -> bool {
+        Self::lookup(issuer, code).is_some_and(|entry| entry.formats.contains(format))
+    }
+}
+
+const CARD: &[DocumentFormat] = &[DocumentFormat::TD1, DocumentFormat::TD2];
+const BOOK: &[DocumentFormat] = &[DocumentFormat::TD3];
+const VISA: &[DocumentFormat] = &[DocumentFormat::MRVA, DocumentFormat::MRVB];
+
+const fn common(code: &'static str, description: &'static str, formats: &'static [DocumentFormat]) -> DocumentTypeEntry {
+    DocumentTypeEntry {
+        issuer: None,
+        code,
+        description,
+        formats,
+    }
+}
+
+const fn issued_by(
+    issuer: &'static str,
+    code: &'static str,
+    description: &'static str,
+    formats: &'static [DocumentFormat],
+) -> DocumentTypeEntry {
+    DocumentTypeEntry {
+        issuer: Some(issuer),
+        code,
+        description,
+        formats,
+    }
+}
+
+static DOCUMENT_TYPES: &[DocumentTypeEntry] = &[
+    // Passports
+    common("P", "Passport", BOOK),
+    common("PA", "Passport", BOOK),
+    common("PD", "Diplomatic passport", BOOK),
+    common("PO", "Official passport", BOOK),
+    common("PS", "Service passport", BOOK),
+    common("PE", "Emergency passport", BOOK),
+    common("PT", "Travel document for refugees, stateless persons or aliens", BOOK),
+    // Official travel documents in card format
+    common("I", "Identity card", CARD),
+    common("ID", "Identity card", CARD),
+    common("IR", "Residence permit", CARD),
+    common("IP", "Residence permit", CARD),
+    common("A", "Official travel document", CARD),
+    common("AC", "Crew member certificate", CARD),
+    common("C", "Official travel document", CARD),
+    // Visas
+    common("V", "Visa", VISA),
+    common("VA", "Visa", &[DocumentFormat::MRVA]),
+    common("VB", "Visa", &[DocumentFormat::MRVB]),
+    common("VC", "Schengen uniform visa", VISA),
+    common("VD", "National long-stay visa", VISA),
+    // Issuer-specific codes, from published specimens (incomplete)
+    issued_by("D", "PC", "Children's passport", BOOK),
+    issued_by("D", "AR", "Residence permit", CARD),
+    issued_by("ITA", "CI", "Electronic identity card", CARD),
+    issued_by("KOR", "PM", "Passport for multiple journeys", BOOK),
+];
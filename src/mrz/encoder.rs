@@ -0,0 +1,544 @@
+use crate::models::{CheckDigits, DocumentDate, DocumentFormat, DocumentType, MrzData, Sex};
+use crate::mrz::{CheckDigitCalculator, CheckedField, MrzLayout, MrzParser, Transliterator};
+use crate::utils::PassportError;
+
+/// Encoder producing ICAO Doc 9303 compliant MRZ lines from structured data.
+///
+/// This is the inverse of `MrzParser`: fields are placed at their format-specific
+/// positions, padded with fillers, names are truncated following Doc 9303 Part 3
+/// and all check digits are computed.
+pub struct MrzEncoder;
+
+impl MrzEncoder {
+    /// Encode MRZ data into the lines of the given document format
+    pub fn encode(data: &MrzData, format: &DocumentFormat) -> Result<Vec<String>, PassportError> {
+        let document_type = match &data.document_type {
+            Some(document_type) => Self::field(&document_type.code, 2, "Document type")?,
+            None => return Err(PassportError::FormatError("Document type is missing".to_string())),
+        };
+        let issuing_country = Self::field(&data.issuing_country, 3, "Issuing country")?;
+        let nationality = Self::field(&data.nationality, 3, "Nationality")?;
+        let date_of_birth = Self::date(&data.date_of_birth, "Date of birth")?;
+        let date_of_expiry = Self::date(&data.date_of_expiry, "Date of expiry")?;
+        let sex = data.sex.unwrap_or(Sex::Unspecified).to_mrz();
+        let optional_data = data.optional_data.as_deref().unwrap_or("");
+
+        let mut lines = match format {
+            DocumentFormat::TD1 => {
+                let (number, number_check, optional_data) =
+                    Self::document_number_with_overflow(&data.document_number, optional_data, 15)?;
+                vec![
+                    format!("{}{}{}{}{}", document_type, issuing_country, number, number_check, optional_data),
+                    format!(
+                        "{}{}{}{}{}{}{}<",
+                        date_of_birth,
+                        Self::check(&date_of_birth)?,
+                        sex,
+                        date_of_expiry,
+                        Self::check(&date_of_expiry)?,
+                        nationality,
+                        Self::field(data.optional_data_2.as_deref().unwrap_or(""), 11, "Optional data 2")?
+                    ),
+                    Self::name(&data.surname, &data.given_names, 30)?,
+                ]
+            }
+            DocumentFormat::TD2 => {
+                let (number, number_check, optional_data) =
+                    Self::document_number_with_overflow(&data.document_number, optional_data, 7)?;
+                vec![
+                    format!("{}{}{}", document_type, issuing_country, Self::name(&data.surname, &data.given_names, 31)?),
+                    format!(
+                        "{}{}{}{}{}{}{}{}{}<",
+                        number,
+                        number_check,
+                        nationality,
+                        date_of_birth,
+                        Self::check(&date_of_birth)?,
+                        sex,
+                        date_of_expiry,
+                        Self::check(&date_of_expiry)?,
+                        optional_data
+                    ),
+                ]
+            }
+            DocumentFormat::TD3 | DocumentFormat::MRVA | DocumentFormat::MRVB => {
+                let line_length = format.mrz_chars_per_line();
+                let number = Self::field(&data.document_number, 9, "Document number")?;
+                let trailing = if *format == DocumentFormat::TD3 {
+                    let personal_number =
+                        Self::field(data.personal_number.as_deref().unwrap_or(""), 14, "Personal number")?;
+                    let personal_number_check = if personal_number.chars().all(|c| c == '<') {
+                        '<'
+                    } else {
+                        Self::check(&personal_number)?
+                    };
+                    format!("{}{}<", personal_number, personal_number_check)
+                } else {
+                    Self::field(optional_data, line_length - 28, "Optional data")?
+                };
+                vec![
+                    format!(
+                        "{}{}{}",
+                        document_type,
+                        issuing_country,
+                        Self::name(&data.surname, &data.given_names, line_length - 5)?
+                    ),
+                    format!(
+                        "{}{}{}{}{}{}{}{}{}",
+                        number,
+                        Self::check(&number)?,
+                        nationality,
+                        date_of_birth,
+                        Self::check(&date_of_birth)?,
+                        sex,
+                        date_of_expiry,
+                        Self::check(&date_of_expiry)?,
+                        trailing
+                    ),
+                ]
+            }
+        };
+
+        // The composite covers the assembled lines, so it is filled in last
+        if let Some(rule) = MrzLayout::check_digit_rules(format)
+            .into_iter()
+            .find(|rule| rule.field == CheckedField::Composite)
+        {
+            let line_refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+            let (composite_data, _) = MrzLayout::check_digit_input(&line_refs, &rule).ok_or_else(|| {
+                PassportError::FormatError("Composite check digit position out of range".to_string())
+            })?;
+            let composite = Self::check(&composite_data)?;
+            lines[rule.check.line].replace_range(rule.check.start..rule.check.end, &composite.to_string());
+        }
+
+        Ok(lines)
+    }
+
+    /// Encode MRZ data and return it re-parsed with its raw lines and check digits populated
+    pub fn encode_data(data: &MrzData, format: &DocumentFormat) -> Result<MrzData, PassportError> {
+        let lines = Self::encode(data, format)?;
+        let line_refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+        MrzParser::parse(&line_refs)
+    }
+
+    /// Uppercase a field, turn spaces into fillers and pad it to its width
+    fn field(value: &str, width: usize, name: &str) -> Result<String, PassportError> {
+        let encoded: String = value
+            .trim()
+            .chars()
+            .map(|c| if c == ' ' { '<' } else { c.to_ascii_uppercase() })
+            .collect();
+
+        if let Some(c) = encoded.chars().find(|c| !(c.is_ascii_uppercase() || c.is_ascii_digit() || *c == '<')) {
+            return Err(PassportError::FormatError(format!(
+                "{} contains character '{}' that cannot be encoded in the MRZ",
+                name, c
+            )));
+        }
+        if encoded.len() > width {
+            return Err(PassportError::FormatError(format!(
+                "{} '{}' exceeds {} characters",
+                name, value, width
+            )));
+        }
+
+        Ok(format!("{:<<width$}", encoded, width = width))
+    }
+
+    /// Document number, its check digit and the optional data field, applying the
+    /// TD1/TD2 overflow rule for numbers longer than nine characters
+    fn document_number_with_overflow(
+        number: &str,
+        optional_data: &str,
+        optional_width: usize,
+    ) -> Result<(String, char, String), PassportError> {
+        let number = number.trim().to_ascii_uppercase();
+        if number.len() <= 9 {
+            let number = Self::field(&number, 9, "Document number")?;
+            let check = Self::check(&number)?;
+            return Ok((number, check, Self::field(optional_data, optional_width, "Optional data")?));
+        }
+
+        let encoded = Self::field(&number, number.len(), "Document number")?;
+        let (head, tail) = encoded.split_at(9);
+        let check = Self::check(&encoded)?;
+        let overflow = format!("{}{}<{}", tail, check, optional_data.trim());
+        Ok((head.to_string(), '<', Self::field(&overflow, optional_width, "Document number overflow")?))
+    }
+
+    /// MRZ YYMMDD form of a date, with fillers for an unknown day or month
+    fn date(value: &DocumentDate, name: &str) -> Result<String, PassportError> {
+        let encoded = value
+            .to_mrz()
+            .ok_or_else(|| PassportError::InvalidDate(format!("{} '{}' cannot be encoded", name, value)))?;
+        if value.year.is_none_or(|year| year < 0)
+            || value.month.is_some_and(|month| !(1..=12).contains(&month))
+            || value.day.is_some_and(|day| !(1..=31).contains(&day))
+        {
+            return Err(PassportError::InvalidDate(format!("{} '{}' cannot be encoded", name, value)));
+        }
+        Ok(encoded)
+    }
+
+    fn check(data: &str) -> Result<char, PassportError> {
+        CheckDigitCalculator::compute(data)
+            .ok_or_else(|| PassportError::FormatError(format!("Cannot compute check digit for '{}'", data)))
+    }
+
+    /// Encode the name field, truncating per Doc 9303 Part 3 when it does not fit.
+    ///
+    /// Names are transliterated with the recommended rendering of each character first.
+    /// Secondary identifier components are shortened from the last one down to their
+    /// initial, then dropped; only then is the primary identifier cut.
+    fn name(primary: &str, secondary: &str, width: usize) -> Result<String, PassportError> {
+        let components = |name: &str| -> Vec<String> {
+            Transliterator::transliterate(name)
+                .split('<')
+                .filter(|part| !part.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let primary = components(primary).join("<");
+        let mut secondary = components(secondary);
+
+        let render = |secondary: &[String]| {
+            if secondary.is_empty() {
+                primary.clone()
+            } else {
+                format!("{}<<{}", primary, secondary.join("<"))
+            }
+        };
+
+        let mut name = render(&secondary);
+        let mut index = secondary.len();
+        while name.len() > width && index > 0 {
+            index -= 1;
+            let excess = name.len() - width;
+            let component = &mut secondary[index];
+            let keep = component.len().saturating_sub(excess).max(1);
+            component.truncate(keep);
+            name = render(&secondary);
+        }
+        while name.len() > width && !secondary.is_empty() {
+            secondary.pop();
+            name = render(&secondary);
+        }
+        name.truncate(width);
+
+        Self::field(&name, width, "Name")
+    }
+}
+
+/// Builder of holder fields for `MrzEncoder`, for callers that do not have an `MrzData`
+#[derive(Debug, Clone, Default)]
+pub struct MrzBuilder {
+    document_type: Option<DocumentType>,
+    issuing_country: String,
+    document_number: String,
+    surname: String,
+    given_names: String,
+    nationality: String,
+    date_of_birth: DocumentDate,
+    sex: Option<Sex>,
+    date_of_expiry: DocumentDate,
+    personal_number: Option<String>,
+    optional_data: Option<String>,
+    optional_data_2: Option<String>,
+}
+
+impl MrzBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Document code such as "P", "PD" or "ID"; an invalid code leaves the type unset
+    pub fn document_type(mut self, value: &str) -> Self {
+        self.document_type = DocumentType::parse(value);
+        self
+    }
+
+    pub fn issuing_country(mut self, value: &str) -> Self {
+        self.issuing_country = value.to_string();
+        self
+    }
+
+    pub fn document_number(mut self, value: &str) -> Self {
+        self.document_number = value.to_string();
+        self
+    }
+
+    pub fn surname(mut self, value: &str) -> Self {
+        self.surname = value.to_string();
+        self
+    }
+
+    pub fn given_names(mut self, value: &str) -> Self {
+        self.given_names = value.to_string();
+        self
+    }
+
+    pub fn nationality(mut self, value: &str) -> Self {
+        self.nationality = value.to_string();
+        self
+    }
+
+    pub fn date_of_birth(mut self, value: DocumentDate) -> Self {
+        self.date_of_birth = value;
+        self
+    }
+
+    pub fn sex(mut self, value: Sex) -> Self {
+        self.sex = Some(value);
+        self
+    }
+
+    pub fn date_of_expiry(mut self, value: DocumentDate) -> Self {
+        self.date_of_expiry = value;
+        self
+    }
+
+    pub fn personal_number(mut self, value: &str) -> Self {
+        self.personal_number = Some(value.to_string());
+        self
+    }
+
+    pub fn optional_data(mut self, value: &str) -> Self {
+        self.optional_data = Some(value.to_string());
+        self
+    }
+
+    pub fn optional_data_2(mut self, value: &str) -> Self {
+        self.optional_data_2 = Some(value.to_string());
+        self
+    }
+
+    /// Holder data with empty check digits, as accepted by `MrzEncoder::encode`
+    pub fn build(self) -> MrzData {
+        MrzData {
+            document_format: None,
+            document_type: self.document_type,
+            issuing_country: self.issuing_country,
+            document_number: self.document_number,
+            surname: self.surname,
+            given_names: self.given_names,
+            nationality: self.nationality,
+            date_of_birth: self.date_of_birth,
+            sex: self.sex,
+            date_of_expiry: self.date_of_expiry,
+            personal_number: self.personal_number,
+            optional_data: self.optional_data,
+            optional_data_2: self.optional_data_2,
+            check_digits: CheckDigits {
+                document_number_check: '<',
+                date_of_birth_check: '<',
+                date_of_expiry_check: '<',
+                personal_number_check: '<',
+                composite_check: '<',
+            },
+            raw_mrz_lines: Vec::new(),
+            corrections: Vec::new(),
+            unreadable: Vec::new(),
+            repairs: Vec::new(),
+            confidences: Vec::new(),
+        }
+    }
+
+    /// Encode the fields into the MRZ lines of a document format
+    pub fn encode(self, format: &DocumentFormat) -> Result<Vec<String>, PassportError> {
+        MrzEncoder::encode(&self.build(), format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> DocumentDate {
+        DocumentDate::new(Some(year), Some(month), Some(day), "")
+    }
+
+    fn specimen(document_type: &str, document_number: &str) -> MrzBuilder {
+        MrzBuilder::new()
+            .document_type(document_type)
+            .issuing_country("UTO")
+            .document_number(document_number)
+            .surname("Eriksson")
+            .given_names("Anna Maria")
+            .nationality("UTO")
+            .date_of_birth(date(1974, 8, 12))
+            .sex(Sex::Female)
+            .date_of_expiry(date(2032, 4, 15))
+    }
+
+    fn parse(lines: &[String]) -> MrzData {
+        let refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+        MrzParser::parse(&refs).expect("encoded MRZ parses")
+    }
+
+    /// Parse the encoded lines and check that every field and check digit survives
+    fn round_trip(builder: MrzBuilder, format: DocumentFormat) -> MrzData {
+        let expected = builder.clone().build();
+        let lines = builder.encode(&format).expect("MRZ encodes");
+        assert_eq!(lines.len(), format.mrz_lines());
+        assert!(lines.iter().all(|l| l.len() == format.mrz_chars_per_line()), "{:?}", lines);
+
+        let parsed = parse(&lines);
+        assert_eq!(parsed.document_format, Some(format.clone()));
+        assert!(parsed.unreadable.is_empty(), "{:?}", parsed.unreadable);
+        assert_eq!(parsed.document_type.as_ref().map(|t| t.code.clone()), expected.document_type.map(|t| t.code));
+        assert_eq!(parsed.issuing_country, expected.issuing_country);
+        assert_eq!(parsed.document_number, expected.document_number.to_ascii_uppercase());
+        assert_eq!(parsed.nationality, expected.nationality);
+        assert_eq!(parsed.sex, expected.sex);
+        for (parsed, expected) in [
+            (&parsed.date_of_birth, &expected.date_of_birth),
+            (&parsed.date_of_expiry, &expected.date_of_expiry),
+        ] {
+            assert_eq!((parsed.year, parsed.month, parsed.day), (expected.year, expected.month, expected.day));
+        }
+
+        let refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+        for rule in MrzLayout::check_digit_rules(&format) {
+            let (data, check) = MrzLayout::check_digit_input(&refs, &rule).expect("check digit located");
+            assert!(CheckDigitCalculator::verify(&data, check), "{} check digit in {:?}", rule.field.name(), lines);
+        }
+        parsed
+    }
+
+    #[test]
+    fn encodes_td3_specimen() {
+        let builder = specimen("P", "L898902C3").personal_number("ZE184226B").date_of_expiry(date(2012, 4, 15));
+        let lines = builder.encode(&DocumentFormat::TD3).unwrap();
+        assert_eq!(
+            lines,
+            ["P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<", "L898902C36UTO7408122F1204159ZE184226B<<<<<10"]
+        );
+    }
+
+    #[test]
+    fn encodes_td1_specimen() {
+        let builder = specimen("I", "D23145890").date_of_expiry(date(2012, 4, 15));
+        let lines = builder.encode(&DocumentFormat::TD1).unwrap();
+        assert_eq!(
+            lines,
+            [
+                "I<UTOD231458907<<<<<<<<<<<<<<<",
+                "7408122F1204159UTO<<<<<<<<<<<6",
+                "ERIKSSON<<ANNA<MARIA<<<<<<<<<<"
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_td2_specimen() {
+        let builder = specimen("I", "D23145890").date_of_expiry(date(2012, 4, 15));
+        let lines = builder.encode(&DocumentFormat::TD2).unwrap();
+        assert_eq!(lines, ["I<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<", "D231458907UTO7408122F1204159<<<<<<<6"]);
+    }
+
+    #[test]
+    fn encodes_visa_specimens() {
+        let visa = || {
+            specimen("V", "L8988901C")
+                .nationality("XXX")
+                .date_of_birth(date(1940, 9, 7))
+                .date_of_expiry(date(1996, 12, 10))
+        };
+        let mrva = visa().optional_data("6ZE184226B").encode(&DocumentFormat::MRVA).unwrap();
+        assert_eq!(
+            mrva,
+            ["V<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<", "L8988901C4XXX4009078F96121096ZE184226B<<<<<<"]
+        );
+        let mrvb = visa().encode(&DocumentFormat::MRVB).unwrap();
+        assert_eq!(mrvb, ["V<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<", "L8988901C4XXX4009078F9612109<<<<<<<<"]);
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        let td3 = round_trip(specimen("P", "L898902C3").personal_number("ZE184226B"), DocumentFormat::TD3);
+        assert_eq!(td3.personal_number.as_deref(), Some("ZE184226B"));
+
+        let td1 = round_trip(specimen("I", "D23145890").optional_data_2("AB12"), DocumentFormat::TD1);
+        assert_eq!(td1.optional_data_2.as_deref(), Some("AB12"));
+
+        round_trip(specimen("I", "D23145890"), DocumentFormat::TD2);
+
+        let mrva = round_trip(specimen("V", "L8988901C").optional_data("6ZE184226B"), DocumentFormat::MRVA);
+        assert_eq!(mrva.optional_data.as_deref(), Some("6ZE184226B"));
+
+        round_trip(specimen("V", "L8988901C"), DocumentFormat::MRVB);
+
+        for parsed in [td3, td1, mrva] {
+            assert_eq!(parsed.surname, "ERIKSSON");
+            assert_eq!(parsed.given_names, "ANNA MARIA");
+        }
+    }
+
+    #[test]
+    fn round_trips_overflowing_document_numbers() {
+        let td1 = round_trip(specimen("I", "D23145890734").optional_data("XY"), DocumentFormat::TD1);
+        assert_eq!(td1.raw_mrz_lines[0], "I<UTOD23145890<7349<XY<<<<<<<<");
+        assert_eq!(td1.check_digits.document_number_check, '9');
+        assert_eq!(td1.optional_data.as_deref(), Some("XY"));
+
+        let td2 = round_trip(specimen("I", "D23145890734"), DocumentFormat::TD2);
+        assert_eq!(&td2.raw_mrz_lines[1][0..10], "D23145890<");
+        assert_eq!(&td2.raw_mrz_lines[1][28..35], "7349<<<");
+
+        // A number too long for the overflow field cannot be encoded
+        assert!(specimen("I", "D231458907341234").encode(&DocumentFormat::TD2).is_err());
+        // Passports and visas have no overflow
+        assert!(specimen("P", "D23145890734").encode(&DocumentFormat::TD3).is_err());
+    }
+
+    #[test]
+    fn truncates_long_names() {
+        let builder = specimen("P", "L898902C3")
+            .surname("Nilavadhanananda")
+            .given_names("Chayapa Dejthamrong Krasuang");
+        let lines = builder.clone().encode(&DocumentFormat::TD3).unwrap();
+        // Secondary identifiers are cut from the last component, filling the field exactly
+        assert_eq!(&lines[0][5..], "NILAVADHANANANDA<<CHAYAPA<DEJTHAMRONG<K");
+        let parsed = round_trip(builder.clone(), DocumentFormat::TD3);
+        assert_eq!(parsed.surname, "NILAVADHANANANDA");
+        assert_eq!(parsed.given_names, "CHAYAPA DEJTHAMRONG K");
+
+        // TD1 has 30 characters: components that no longer fit are dropped
+        let td1 = round_trip(builder.document_number("D23145890"), DocumentFormat::TD1);
+        assert_eq!(td1.raw_mrz_lines[2].len(), 30);
+        assert!(td1.raw_mrz_lines[2].starts_with("NILAVADHANANANDA<<"));
+        assert!(!td1.raw_mrz_lines[2].ends_with('<'));
+
+        // A primary identifier longer than the field is cut
+        let long = specimen("I", "D23145890").surname("Wolfeschlegelsteinhausenbergerdorff").given_names("");
+        let lines = long.encode(&DocumentFormat::TD1).unwrap();
+        assert_eq!(lines[2], "WOLFESCHLEGELSTEINHAUSENBERGER");
+    }
+
+    #[test]
+    fn places_fillers() {
+        let lines = specimen("P", "AB12").sex(Sex::Unspecified).encode(&DocumentFormat::TD3).unwrap();
+        // Short document numbers are padded, an empty personal number has a filler check digit
+        assert_eq!(&lines[1][0..9], "AB12<<<<<");
+        assert_eq!(lines[1].as_bytes()[20], b'<');
+        assert_eq!(&lines[1][28..43], "<<<<<<<<<<<<<<<");
+        assert_eq!(lines[0].as_bytes()[1], b'<');
+
+        // Spaces in names and unknown date components become fillers
+        let lines = specimen("P", "AB12")
+            .surname("van der Berg")
+            .date_of_birth(DocumentDate::new(Some(1974), None, None, ""))
+            .encode(&DocumentFormat::TD3)
+            .unwrap();
+        assert!(lines[0][5..].starts_with("VAN<DER<BERG<<ANNA<MARIA<"));
+        assert_eq!(&lines[1][13..19], "74<<<<");
+    }
+
+    #[test]
+    fn rejects_unencodable_values() {
+        assert!(specimen("P", "L8989-2C3").encode(&DocumentFormat::TD3).is_err());
+        assert!(specimen("P", "L898902C3").issuing_country("UTOP").encode(&DocumentFormat::TD3).is_err());
+        assert!(MrzBuilder::new().encode(&DocumentFormat::TD3).is_err());
+    }
+}
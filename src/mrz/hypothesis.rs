@@ -0,0 +1,194 @@
+use crate::models::{DocumentFormat, FieldConfidence, MrzData};
+use crate::mrz::{
+    CheckDigitCalculator, CorrectedMrz, CountryRegistry, DocumentTypeRegistry, LocatedMrz, MrzCorrector, MrzLayout,
+    MrzParser, ParseOptions,
+};
+use crate::utils::PassportError;
+
+/// A candidate reading of an MRZ with the evidence it was ranked on
+#[derive(Debug, Clone)]
+pub struct MrzHypothesis {
+    pub data: MrzData,
+    /// Weighted sum of the three components below, from 0 to 1
+    pub score: f64,
+    /// Mean OCR confidence of the characters as read; a changed character counts with the
+    /// confidence that it was misread instead (0 to 1)
+    pub ocr_confidence: f64,
+    /// Share of check digits that hold (0 to 1)
+    pub check_digit_agreement: f64,
+    /// Share of fields that could be read and of codes found in the registries (0 to 1)
+    pub plausibility: f64,
+}
+
+/// Builds and ranks alternative readings of located MRZ blocks, so ambiguous scans can be
+/// resolved by an operator instead of a re-scan
+pub struct MrzHypotheses;
+
+impl MrzHypotheses {
+    const OCR_CONFIDENCE_WEIGHT: f64 = 0.3;
+    const CHECK_DIGIT_WEIGHT: f64 = 0.5;
+    const PLAUSIBILITY_WEIGHT: f64 = 0.2;
+
+    /// Every reading of a located block (see `MrzCorrector::alternatives`), given the OCR
+    /// confidence (0 to 100) of every character of its lines. A reading whose country codes
+    /// were snapped to the registry is also kept with the codes as read, which ranks lower
+    /// since its codes are not registered
+    pub fn from_located(located: &LocatedMrz, confidences: &[Vec<f32>], options: &ParseOptions) -> Vec<MrzHypothesis> {
+        let line_refs: Vec<&str> = located.lines.iter().map(|l| l.as_str()).collect();
+        MrzCorrector::alternatives(&line_refs, &located.format)
+            .iter()
+            .flat_map(|reading| {
+                let snapped = Self::read(located, reading, confidences, options).ok();
+                let as_read = Self::parse_reading(located, reading, confidences, options, false)
+                    .ok()
+                    .filter(|data| snapped.as_ref().is_some_and(|s| s.raw_mrz_lines != data.raw_mrz_lines));
+                snapped.into_iter().chain(as_read)
+            })
+            .map(|data| Self::score(data, located, confidences))
+            .collect()
+    }
+
+    /// Keep the `count` best hypotheses with distinct MRZ lines, best first
+    pub fn rank(mut hypotheses: Vec<MrzHypothesis>, count: usize) -> Vec<MrzHypothesis> {
+        hypotheses.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let mut ranked: Vec<MrzHypothesis> = Vec::new();
+        for hypothesis in hypotheses {
+            if ranked.len() == count {
+                break;
+            }
+            if !ranked.iter().any(|r| r.data.raw_mrz_lines == hypothesis.data.raw_mrz_lines) {
+                ranked.push(hypothesis);
+            }
+        }
+        ranked
+    }
+
+    /// Parse one reading of a located block: country codes are snapped to the registry, and
+    /// corrections, re-alignment repairs and field confidences are attached
+    pub fn read(
+        located: &LocatedMrz,
+        reading: &CorrectedMrz,
+        confidences: &[Vec<f32>],
+        options: &ParseOptions,
+    ) -> Result<MrzData, PassportError> {
+        Self::parse_reading(located, reading, confidences, options, true)
+    }
+
+    /// Parse one reading of a located block, snapping its country codes or not
+    fn parse_reading(
+        located: &LocatedMrz,
+        reading: &CorrectedMrz,
+        confidences: &[Vec<f32>],
+        options: &ParseOptions,
+        snap_codes: bool,
+    ) -> Result<MrzData, PassportError> {
+        let reading_refs: Vec<&str> = reading.lines.iter().map(|l| l.as_str()).collect();
+        // Country codes are not check-digit protected; constrain misread ones to the ICAO registry
+        let (snapped, code_corrections) = if snap_codes {
+            CountryRegistry::snap_mrz(&reading_refs, &located.format)
+        } else {
+            (reading.lines.clone(), Vec::new())
+        };
+        let snapped_refs: Vec<&str> = snapped.iter().map(|l| l.as_str()).collect();
+
+        let mut data = MrzParser::parse_with_options(&snapped_refs, options)?;
+        data.corrections = reading.corrections.clone();
+        data.corrections.extend(code_corrections);
+        // Re-alignment happened before any field was sliced, so it is reported per line
+        data.repairs.splice(0..0, located.edits.iter().map(|edit| edit.to_repair()));
+        data.confidences = Self::field_confidences(&snapped_refs, confidences, &located.format);
+        Ok(data)
+    }
+
+    /// Confidence of every MRZ field and check digit from the confidences of its lines
+    pub fn field_confidences(lines: &[&str], confidences: &[Vec<f32>], format: &DocumentFormat) -> Vec<FieldConfidence> {
+        let check_digit_spans = MrzLayout::check_digit_rules(format)
+            .into_iter()
+            .map(|rule| (format!("{} check digit", rule.field.name()), rule.check));
+        MrzLayout::field_spans(format)
+            .into_iter()
+            .map(|(field, span)| (field.to_string(), span))
+            .chain(check_digit_spans)
+            .filter_map(|(field, span)| {
+                let characters: Vec<(char, f32)> = span
+                    .extract(lines)?
+                    .chars()
+                    .zip(confidences.get(span.line)?.get(span.start..span.end)?.iter().copied())
+                    .collect();
+                Some(FieldConfidence::new(&field, &characters))
+            })
+            .collect()
+    }
+
+    fn score(data: MrzData, located: &LocatedMrz, confidences: &[Vec<f32>]) -> MrzHypothesis {
+        let format = &located.format;
+
+        let mut confidence_sum = 0.0;
+        let mut characters = 0;
+        for ((read, final_line), line_confidences) in located.lines.iter().zip(&data.raw_mrz_lines).zip(confidences) {
+            for ((before, after), confidence) in read.chars().zip(final_line.chars()).zip(line_confidences) {
+                let confidence = f64::from(*confidence) / 100.0;
+                confidence_sum += if before == after { confidence } else { 1.0 - confidence };
+                characters += 1;
+            }
+        }
+        let ocr_confidence = if characters == 0 { 0.0 } else { confidence_sum / characters as f64 };
+
+        let lines: Vec<&str> = data.raw_mrz_lines.iter().map(|l| l.as_str()).collect();
+        let rules = MrzLayout::check_digit_rules(format);
+        let holding = rules
+            .iter()
+            .filter(|rule| {
+                MrzLayout::check_digit_input(&lines, rule)
+                    .is_some_and(|(input, check)| CheckDigitCalculator::verify(&input, check))
+            })
+            .count();
+        let check_digit_agreement = holding as f64 / rules.len() as f64;
+
+        let fields = MrzLayout::field_spans(format);
+        let readable = fields.iter().filter(|(field, _)| !data.is_unreadable(field)).count();
+        let registered = [&data.issuing_country, &data.nationality]
+            .iter()
+            .filter(|code| CountryRegistry::is_valid(code))
+            .count()
+            + usize::from(
+                data.document_type
+                    .as_ref()
+                    .is_some_and(|t| DocumentTypeRegistry::is_known(&data.issuing_country, &t.code)),
+            );
+        let plausibility = (readable + registered) as f64 / (fields.len() + 3) as f64;
+
+        MrzHypothesis {
+            score: Self::OCR_CONFIDENCE_WEIGHT * ocr_confidence
+                + Self::CHECK_DIGIT_WEIGHT * check_digit_agreement
+                + Self::PLAUSIBILITY_WEIGHT * plausibility,
+            ocr_confidence,
+            check_digit_agreement,
+            plausibility,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mrz::MrzLocator;
+
+    #[test]
+    fn keeps_the_unsnapped_reading_below_the_snapped_one() {
+        let text = "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<\nL898902C36UT07408122F1204159ZE184226B<<<<<10";
+        let located = MrzLocator::locate(text).expect("MRZ located");
+        let confidences: Vec<Vec<f32>> = located.lines.iter().map(|l| vec![90.0; l.len()]).collect();
+        let hypotheses = MrzHypotheses::from_located(&located, &confidences, &ParseOptions::default());
+        let ranked = MrzHypotheses::rank(hypotheses, 5);
+
+        assert_eq!(ranked[0].data.nationality, "UTO");
+        assert!(ranked[0].data.corrections.iter().any(|c| c.field == "Nationality"));
+        let as_read = ranked
+            .iter()
+            .position(|h| h.data.raw_mrz_lines[1].contains("UT07"))
+            .expect("reading with the code as read is kept");
+        assert!(as_read > 0);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::models::DocumentFormat;
+
+/// Fields protected by an MRZ check digit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CheckedField {
+    DocumentNumber,
+    DateOfBirth,
+    DateOfExpiry,
+    PersonalNumber,
+    Composite,
+}
+
+impl CheckedField {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CheckedField::DocumentNumber => "Document number",
+            CheckedField::DateOfBirth => "Date of birth",
+            CheckedField::DateOfExpiry => "Date of expiry",
+            CheckedField::PersonalNumber => "Personal number",
+            CheckedField::Composite => "Composite",
+        }
+    }
+}
+
+/// Characters an MRZ field may contain besides the `<` filler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MrzCharset {
+    Alpha,
+    Numeric,
+    Alphanumeric,
+}
+
+impl MrzCharset {
+    pub fn allows(&self, c: char) -> bool {
+        match self {
+            MrzCharset::Alpha => c.is_ascii_uppercase() || c == '<',
+            MrzCharset::Numeric => c.is_ascii_digit() || c == '<',
+            MrzCharset::Alphanumeric => c.is_ascii_uppercase() || c.is_ascii_digit() || c == '<',
+        }
+    }
+}
+
+/// A contiguous range of characters on one MRZ line (0-based, end exclusive)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MrzSpan {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl MrzSpan {
+    pub const fn new(line: usize, start: usize, end: usize) -> Self {
+        MrzSpan { line, start, end }
+    }
+
+    /// Characters covered by the span, or None if the line is too short
+    pub fn extract<'a>(&self, lines: &[&'a str]) -> Option<&'a str> {
+        lines.get(self.line)?.get(self.start..self.end)
+    }
+}
+
+/// Position of a check digit and the data it protects
+#[derive(Debug, Clone)]
+pub struct CheckDigitRule {
+    pub field: CheckedField,
+    /// Characters allowed in the protected data (the check digit itself is numeric)
+    pub charset: MrzCharset,
+    pub spans: Vec<MrzSpan>,
+    pub check: MrzSpan,
+    /// Optional data span a long document number overflows into (TD1/TD2)
+    pub overflow: Option<MrzSpan>,
+}
+
+/// Field positions of each MRZ layout according to ICAO Doc 9303 Parts 4-7
+pub struct MrzLayout;
+
+impl MrzLayout {
+    /// Check digit rules for a document format, in the order they appear in the MRZ
+    pub fn check_digit_rules(format: &DocumentFormat) -> Vec<CheckDigitRule> {
+        let rule = |field, spans: Vec<MrzSpan>, check, overflow| CheckDigitRule {
+            field,
+            charset: match field {
+                CheckedField::DateOfBirth | CheckedField::DateOfExpiry => MrzCharset::Numeric,
+                _ => MrzCharset::Alphanumeric,
+            },
+            spans,
+            check,
+            overflow,
+        };
+
+        match format {
+            DocumentFormat::TD1 => vec![
+                rule(
+                    CheckedField::DocumentNumber,
+                    vec![MrzSpan::new(0, 5, 14)],
+                    MrzSpan::new(0, 14, 15),
+                    Some(MrzSpan::new(0, 15, 30)),
+                ),
+                rule(CheckedField::DateOfBirth, vec![MrzSpan::new(1, 0, 6)], MrzSpan::new(1, 6, 7), None),
+                rule(CheckedField::DateOfExpiry, vec![MrzSpan::new(1, 8, 14)], MrzSpan::new(1, 14, 15), None),
+                rule(
+                    CheckedField::Composite,
+                    vec![
+                        MrzSpan::new(0, 5, 30),
+                        MrzSpan::new(1, 0, 7),
+                        MrzSpan::new(1, 8, 15),
+                        MrzSpan::new(1, 18, 29),
+                    ],
+                    MrzSpan::new(1, 29, 30),
+                    None,
+                ),
+            ],
+            DocumentFormat::TD2 => vec![
+                rule(
+                    CheckedField::DocumentNumber,
+                    vec![MrzSpan::new(1, 0, 9)],
+                    MrzSpan::new(1, 9, 10),
+                    Some(MrzSpan::new(1, 28, 35)),
+                ),
+                rule(CheckedField::DateOfBirth, vec![MrzSpan::new(1, 13, 19)], MrzSpan::new(1, 19, 20), None),
+                rule(CheckedField::DateOfExpiry, vec![MrzSpan::new(1, 21, 27)], MrzSpan::new(1, 27, 28), None),
+                rule(
+                    CheckedField::Composite,
+                    vec![MrzSpan::new(1, 0, 10), MrzSpan::new(1, 13, 20), MrzSpan::new(1, 21, 35)],
+                    MrzSpan::new(1, 35, 36),
+                    None,
+                ),
+            ],
+            DocumentFormat::TD3 => vec![
+                rule(CheckedField::DocumentNumber, vec![MrzSpan::new(1, 0, 9)], MrzSpan::new(1, 9, 10), None),
+                rule(CheckedField::DateOfBirth, vec![MrzSpan::new(1, 13, 19)], MrzSpan::new(1, 19, 20), None),
+                rule(CheckedField::DateOfExpiry, vec![MrzSpan::new(1, 21, 27)], MrzSpan::new(1, 27, 28), None),
+                rule(CheckedField::PersonalNumber, vec![MrzSpan::new(1, 28, 42)], MrzSpan::new(1, 42, 43), None),
+                rule(
+                    CheckedField::Composite,
+                    vec![MrzSpan::new(1, 0, 10), MrzSpan::new(1, 13, 20), MrzSpan::new(1, 21, 43)],
+                    MrzSpan::new(1, 43, 44),
+                    None,
+                ),
+            ],
+            DocumentFormat::MRVA | DocumentFormat::MRVB => vec![
+                rule(CheckedField::DocumentNumber, vec![MrzSpan::new(1, 0, 9)], MrzSpan::new(1, 9, 10), None),
+                rule(CheckedField::DateOfBirth, vec![MrzSpan::new(1, 13, 19)], MrzSpan::new(1, 19, 20), None),
+                rule(CheckedField::DateOfExpiry, vec![MrzSpan::new(1, 21, 27)], MrzSpan::new(1, 27, 28), None),
+            ],
+        }
+    }
+
+    /// Span of the name field (primary and secondary identifiers)
+    pub fn name_span(format: &DocumentFormat) -> MrzSpan {
+        match format {
+            DocumentFormat::TD1 => MrzSpan::new(2, 0, 30),
+            DocumentFormat::TD2 | DocumentFormat::MRVB => MrzSpan::new(0, 5, 36),
+            DocumentFormat::TD3 | DocumentFormat::MRVA => MrzSpan::new(0, 5, 44),
+        }
+    }
+
+    /// Spans of the date of birth and date of expiry (YYMMDD)
+    pub fn date_spans(format: &DocumentFormat) -> [MrzSpan; 2] {
+        match format {
+            DocumentFormat::TD1 => [MrzSpan::new(1, 0, 6), MrzSpan::new(1, 8, 14)],
+            _ => [MrzSpan::new(1, 13, 19), MrzSpan::new(1, 21, 27)],
+        }
+    }
+
+    /// Span of the sex character
+    pub fn sex_span(format: &DocumentFormat) -> MrzSpan {
+        match format {
+            DocumentFormat::TD1 => MrzSpan::new(1, 7, 8),
+            _ => MrzSpan::new(1, 20, 21),
+        }
+    }
+
+    /// Spans of the issuing state and nationality codes, labelled by field
+    pub fn code_spans(format: &DocumentFormat) -> [(&'static str, MrzSpan); 2] {
+        let nationality = match format {
+            DocumentFormat::TD1 => MrzSpan::new(1, 15, 18),
+            _ => MrzSpan::new(1, 10, 13),
+        };
+        [("Issuing country", MrzSpan::new(0, 2, 5)), ("Nationality", nationality)]
+    }
+
+    /// Spans of every data field (check digits excluded), labelled as in `MrzData` issues
+    pub fn field_spans(format: &DocumentFormat) -> Vec<(&'static str, MrzSpan)> {
+        let [issuing_country, nationality] = Self::code_spans(format);
+        let [date_of_birth, date_of_expiry] = Self::date_spans(format);
+        let mut spans = vec![
+            ("Document type", MrzSpan::new(0, 0, 2)),
+            issuing_country,
+            ("Name", Self::name_span(format)),
+            nationality,
+            ("Date of birth", date_of_birth),
+            ("Sex", Self::sex_span(format)),
+            ("Date of expiry", date_of_expiry),
+        ];
+        match format {
+            DocumentFormat::TD1 => spans.extend([
+                ("Document number", MrzSpan::new(0, 5, 14)),
+                ("Optional data", MrzSpan::new(0, 15, 30)),
+                ("Optional data 2", MrzSpan::new(1, 18, 29)),
+            ]),
+            DocumentFormat::TD2 => spans.extend([
+                ("Document number", MrzSpan::new(1, 0, 9)),
+                ("Optional data", MrzSpan::new(1, 28, 35)),
+            ]),
+            DocumentFormat::TD3 => spans.extend([
+                ("Document number", MrzSpan::new(1, 0, 9)),
+                ("Personal number", MrzSpan::new(1, 28, 42)),
+            ]),
+            DocumentFormat::MRVA | DocumentFormat::MRVB => spans.extend([
+                ("Document number", MrzSpan::new(1, 0, 9)),
+                ("Optional data", MrzSpan::new(1, 28, format.mrz_chars_per_line())),
+            ]),
+        }
+        spans
+    }
+
+    /// Data protected by a check digit rule and the check digit found in the MRZ.
+    ///
+    /// A filler in the document number check digit position means the number continues
+    /// in the optional data field; the last character before the next filler is then
+    /// the check digit.
+    pub fn check_digit_input(lines: &[&str], rule: &CheckDigitRule) -> Option<(String, char)> {
+        let mut data = String::new();
+        for span in &rule.spans {
+            data.push_str(span.extract(lines)?);
+        }
+        let check = rule.check.extract(lines)?.chars().next()?;
+
+        if let Some(overflow) = rule.overflow.and_then(|span| span.extract(lines)) {
+            if check == '<' && !overflow.starts_with('<') {
+                let end = overflow.find('<').unwrap_or(overflow.len());
+                let (tail, overflow_check) = overflow[..end].split_at(end - 1);
+                data.push_str(tail);
+                return Some((data, overflow_check.chars().next()?));
+            }
+        }
+
+        Some((data, check))
+    }
+}
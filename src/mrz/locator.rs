@@ -0,0 +1,198 @@
+use crate::models::{DocumentFormat, DocumentType};
+use crate::mrz::{AlignmentEdit, CheckDigitCalculator, CountryRegistry, MrzAligner, MrzCorrector, MrzLayout, MrzParser};
+
+/// A block of consecutive OCR lines read as the MRZ of a document format
+#[derive(Debug, Clone)]
+pub struct LocatedMrz {
+    pub format: DocumentFormat,
+    /// Lines in their original order, re-aligned to the format's line length with characters
+    /// outside the MRZ charset replaced by fillers
+    pub lines: Vec<String>,
+    /// Fillers inserted and characters removed to re-align the lines
+    pub edits: Vec<AlignmentEdit>,
+    /// Index of every line of the block in the OCR text
+    pub source_lines: Vec<usize>,
+    /// Plausibility of the block, from 0 to 1
+    pub score: f64,
+}
+
+/// Locates the MRZ in OCR text.
+///
+/// Every group of consecutive non-blank lines whose count and lengths fit a document format
+/// is a candidate. Candidates keep the order of the text and are scored by charset conformance,
+/// check digits (after OCR correction) and the plausibility of the document and country codes.
+pub struct MrzLocator;
+
+impl MrzLocator {
+    /// Lines may be this many characters shorter or longer than the format requires
+    const LENGTH_TOLERANCE: usize = 2;
+    /// Minimum share of MRZ charset characters on every line of a candidate
+    const MIN_CHARSET_RATIO: f64 = 0.9;
+
+    const CHARSET_WEIGHT: f64 = 0.3;
+    const CHECK_DIGIT_WEIGHT: f64 = 0.5;
+    const STRUCTURE_WEIGHT: f64 = 0.2;
+    /// Score deducted per character a line had to be padded or truncated
+    const LENGTH_PENALTY: f64 = 0.01;
+
+    /// Best-scoring MRZ block in the text
+    pub fn locate(text: &str) -> Option<LocatedMrz> {
+        Self::candidates(text).into_iter().next()
+    }
+
+    /// Every plausible MRZ block in the text, best first
+    pub fn candidates(text: &str) -> Vec<LocatedMrz> {
+        // Blank lines between MRZ lines are common in OCR output, so only non-blank lines count
+        let lines: Vec<(usize, String)> = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| {
+                let cleaned: String = line.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_uppercase()).collect();
+                (index, cleaned)
+            })
+            .filter(|(_, line)| !line.is_empty())
+            .collect();
+
+        let mut candidates = Vec::new();
+        // TD3 and MRV-A (and TD2 and MRV-B) share a layout; the parser tells them apart
+        for (line_count, line_length) in [(2, 44), (2, 36), (3, 30)] {
+            for window in lines.windows(line_count) {
+                if let Some(candidate) = Self::candidate(window, line_length) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        candidates
+    }
+
+    fn candidate(window: &[(usize, String)], line_length: usize) -> Option<LocatedMrz> {
+        let mut charset_ratio = 0.0;
+        let mut length_deviation = 0;
+        for (_, line) in window {
+            let length = line.chars().count();
+            if length.abs_diff(line_length) > Self::LENGTH_TOLERANCE {
+                return None;
+            }
+            let ratio = line.chars().filter(|c| Self::is_mrz_char(*c)).count() as f64 / length as f64;
+            if ratio < Self::MIN_CHARSET_RATIO {
+                return None;
+            }
+            charset_ratio += ratio / window.len() as f64;
+            length_deviation += length.abs_diff(line_length);
+        }
+
+        let sanitized: Vec<String> = window.iter().map(|(_, line)| Self::sanitize(line)).collect();
+        let sanitized_refs: Vec<&str> = sanitized.iter().map(|l| l.as_str()).collect();
+        let fitted: Vec<String> = sanitized.iter().map(|l| Self::fit(l, line_length)).collect();
+        let fitted_refs: Vec<&str> = fitted.iter().map(|l| l.as_str()).collect();
+        let format = MrzParser::detect_format(&fitted_refs)?;
+
+        // Dropped or stray characters shift the fields after them, so re-align rather than pad
+        let aligned = MrzAligner::align(&sanitized_refs, &format);
+        let lines = aligned.lines;
+        let line_refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+
+        let score = Self::CHARSET_WEIGHT * charset_ratio
+            + Self::CHECK_DIGIT_WEIGHT * Self::check_digit_ratio(&line_refs, &format)
+            + Self::STRUCTURE_WEIGHT * Self::structure_ratio(&line_refs, &format)
+            - Self::LENGTH_PENALTY * length_deviation as f64;
+
+        Some(LocatedMrz {
+            format,
+            lines,
+            edits: aligned.edits,
+            source_lines: window.iter().map(|(index, _)| *index).collect(),
+            score: score.max(0.0),
+        })
+    }
+
+    /// Share of check digits that hold once OCR confusions are corrected
+    fn check_digit_ratio(lines: &[&str], format: &DocumentFormat) -> f64 {
+        let corrected = MrzCorrector::correct(lines, format);
+        let corrected_refs: Vec<&str> = corrected.lines.iter().map(|l| l.as_str()).collect();
+        let rules = MrzLayout::check_digit_rules(format);
+        let passed = rules
+            .iter()
+            .filter(|rule| {
+                MrzLayout::check_digit_input(&corrected_refs, rule)
+                    .is_some_and(|(data, check)| CheckDigitCalculator::verify(&data, check))
+            })
+            .count();
+        passed as f64 / rules.len() as f64
+    }
+
+    /// Share of the document code and country codes that are valid
+    fn structure_ratio(lines: &[&str], format: &DocumentFormat) -> f64 {
+        let document_type = lines[0].get(0..2).and_then(DocumentType::parse);
+        let mut valid = usize::from(document_type.is_some_and(|t| t.fits_format(format)));
+        for (_, span) in MrzLayout::code_spans(format) {
+            valid += usize::from(span.extract(lines).is_some_and(CountryRegistry::is_valid));
+        }
+        valid as f64 / 3.0
+    }
+
+    /// Replace anything outside the MRZ charset with a filler so the line can be sliced into fields
+    fn sanitize(line: &str) -> String {
+        line.chars().map(|c| if Self::is_mrz_char(c) { c } else { '<' }).collect()
+    }
+
+    /// Pad with fillers or truncate a line to the expected length
+    fn fit(line: &str, line_length: usize) -> String {
+        line.chars().chain(std::iter::repeat('<')).take(line_length).collect()
+    }
+
+    fn is_mrz_char(c: char) -> bool {
+        c.is_ascii_uppercase() || c.is_ascii_digit() || c == '<'
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TD1: [&str; 3] = [
+        "I<UTOD231458907<<<<<<<<<<<<<<<",
+        "7408122F1204159UTO<<<<<<<<<<<6",
+        "ERIKSSON<<ANNA<MARIA<<<<<<<<<<",
+    ];
+
+    #[test]
+    fn finds_a_td1_block_among_noise_lines() {
+        let text = format!(
+            "UTOPIA IDENTITY CARD\nSurname ERIKSSON\n\n{}\n\n{}\n{}\nSignature",
+            TD1[0], TD1[1], TD1[2]
+        );
+        let located = MrzLocator::locate(&text).expect("MRZ located");
+        assert_eq!(located.format, DocumentFormat::TD1);
+        assert_eq!(located.lines, TD1);
+        assert_eq!(located.source_lines, vec![3, 5, 6]);
+        assert!(located.edits.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_line_order_of_the_text() {
+        // Lines out of order do not form a valid block; they are not reordered to fit
+        let text = format!("{}\n{}\n{}", TD1[1], TD1[0], TD1[2]);
+        let shuffled = MrzLocator::candidates(&text);
+        assert!(shuffled.iter().all(|located| located.lines != TD1));
+
+        let text = format!("{}\n{}\n{}", TD1[0], TD1[1], TD1[2]);
+        let located = MrzLocator::locate(&text).expect("MRZ located");
+        assert!(shuffled.iter().all(|candidate| candidate.score < located.score));
+    }
+
+    #[test]
+    fn cleans_ocr_spacing_and_case() {
+        let text = "p<uto eriksson<<anna<maria<<<<<<<<<<<<<<<<<<<\nL898902C36UTO7408122F1204159ZE184226B<<<<<10";
+        let located = MrzLocator::locate(text).expect("MRZ located");
+        assert_eq!(located.format, DocumentFormat::TD3);
+        assert_eq!(located.lines[0], "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<");
+    }
+
+    #[test]
+    fn finds_nothing_in_plain_text() {
+        assert!(MrzLocator::locate("PASSPORT\nUTOPIA\nERIKSSON ANNA MARIA").is_none());
+    }
+}
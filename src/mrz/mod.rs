@@ -0,0 +1,25 @@
+pub mod alignment;
+pub mod check_digit;
+pub mod correction;
+pub mod country;
+pub mod date_policy;
+pub mod document_type;
+pub mod encoder;
+pub mod hypothesis;
+pub mod layout;
+pub mod locator;
+pub mod parser;
+pub mod transliteration;
+
+pub use alignment::{AlignedMrz, AlignmentEdit, MrzAligner};
+pub use check_digit::CheckDigitCalculator;
+pub use correction::{CorrectedMrz, MrzCorrector};
+pub use country::{CodeKind, CountryCode, CountryRegistry};
+pub use date_policy::{DateKind, DatePolicy};
+pub use document_type::{DocumentTypeEntry, DocumentTypeRegistry};
+pub use encoder::{MrzBuilder, MrzEncoder};
+pub use hypothesis::{MrzHypotheses, MrzHypothesis};
+pub use layout::{CheckDigitRule, CheckedField, MrzCharset, MrzLayout, MrzSpan};
+pub use locator::{LocatedMrz, MrzLocator};
+pub use parser::{MrzParser, ParseMode, ParseOptions};
+pub use transliteration::Transliterator;
@@ -0,0 +1,584 @@
+use crate::models::{CheckDigits, DocumentDate, DocumentFormat, DocumentType, FieldRepair, MrzData, Sex, UnreadableField};
+use crate::mrz::{DateKind, DatePolicy};
+use crate::utils::PassportError;
+
+/// How the parser treats field values that are not valid for their position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Invalid fields are left empty and reported in `MrzData::unreadable`
+    #[default]
+    Strict,
+    /// OCR confusions and out-of-range values are repaired and reported in `MrzData::repairs`
+    Lenient,
+}
+
+/// Options controlling how MRZ fields are interpreted
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub mode: ParseMode,
+    pub date_policy: DatePolicy,
+}
+
+/// Parser for MRZ text that has already been read, independent of OCR.
+///
+/// Accepts the MRZ lines as produced by swipe readers, stored records or
+/// manual entry and slices them according to ICAO Doc 9303.
+pub struct MrzParser;
+
+impl MrzParser {
+    /// Parse MRZ lines into structured MRZ data, detecting the document format
+    pub fn parse(lines: &[&str]) -> Result<MrzData, PassportError> {
+        Self::parse_with_options(lines, &ParseOptions::default())
+    }
+
+    /// Parse MRZ lines, resolving two-digit years with the given date policy
+    pub fn parse_with_policy(lines: &[&str], policy: &DatePolicy) -> Result<MrzData, PassportError> {
+        let options = ParseOptions {
+            mode: ParseMode::Strict,
+            date_policy: policy.clone(),
+        };
+        Self::parse_with_options(lines, &options)
+    }
+
+    /// Parse MRZ lines with the given parse mode and date policy.
+    ///
+    /// Only a malformed layout is an error; fields that cannot be read are reported in the
+    /// returned data instead of being replaced with made-up values.
+    pub fn parse_with_options(lines: &[&str], options: &ParseOptions) -> Result<MrzData, PassportError> {
+        let lines = Self::normalize_lines(lines)?;
+        let line_refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+
+        let format = Self::detect_format(&line_refs).ok_or_else(|| {
+            PassportError::MrzParsingError(format!(
+                "Unrecognized MRZ layout: {} line(s) of length {:?}",
+                lines.len(),
+                lines.iter().map(|l| l.len()).collect::<Vec<_>>()
+            ))
+        })?;
+
+        let mut reader = FieldReader::new(options);
+        let mut data = match format {
+            DocumentFormat::TD1 => Self::parse_td1(&line_refs, &mut reader),
+            DocumentFormat::TD2 => Self::parse_td2(&line_refs, &mut reader),
+            DocumentFormat::TD3 => Self::parse_td3(&line_refs, &mut reader),
+            DocumentFormat::MRVA | DocumentFormat::MRVB => Self::parse_mrv(&line_refs, format, &mut reader),
+        };
+        data.unreadable = reader.unreadable;
+        data.repairs = reader.repairs;
+        Ok(data)
+    }
+
+    /// Detect the document format from the number and length of MRZ lines
+    pub fn detect_format(lines: &[&str]) -> Option<DocumentFormat> {
+        let is_visa = lines.first().is_some_and(|l| l.starts_with('V'));
+        let candidates: &[DocumentFormat] = if is_visa {
+            &[DocumentFormat::MRVA, DocumentFormat::MRVB]
+        } else {
+            &[DocumentFormat::TD1, DocumentFormat::TD2, DocumentFormat::TD3]
+        };
+
+        candidates
+            .iter()
+            .find(|format| {
+                lines.len() == format.mrz_lines()
+                    && lines.iter().all(|l| l.len() == format.mrz_chars_per_line())
+            })
+            .cloned()
+    }
+
+    /// Strip whitespace, uppercase and drop blank lines, rejecting characters outside the MRZ charset
+    fn normalize_lines(lines: &[&str]) -> Result<Vec<String>, PassportError> {
+        let normalized: Vec<String> = lines
+            .iter()
+            .map(|line| {
+                line.chars()
+                    .filter(|c| !c.is_whitespace())
+                    .map(|c| c.to_ascii_uppercase())
+                    .collect::<String>()
+            })
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        if normalized.is_empty() {
+            return Err(PassportError::MrzParsingError("No MRZ lines provided".to_string()));
+        }
+
+        for (index, line) in normalized.iter().enumerate() {
+            if let Some(c) = line.chars().find(|c| !Self::is_mrz_char(*c)) {
+                return Err(PassportError::MrzParsingError(format!(
+                    "Invalid character '{}' in MRZ line {}",
+                    c,
+                    index + 1
+                )));
+            }
+        }
+
+        Ok(normalized)
+    }
+
+    fn is_mrz_char(c: char) -> bool {
+        c.is_ascii_uppercase() || c.is_ascii_digit() || c == '<'
+    }
+
+    // Line 1: Positions 1-2 (Document type), 3-5 (Issuing country), 6-14 (Document number),
+    // 15 (Check digit), 16-30 (Optional data)
+    // Line 2: Positions 1-6 (Birth date), 7 (Check digit), 8 (Sex), 9-14 (Expiry date),
+    // 15 (Check digit), 16-18 (Nationality), 19-29 (Optional data), 30 (Composite check digit)
+    // Line 3: Positions 1-30 (Name)
+    fn parse_td1(lines: &[&str], reader: &mut FieldReader) -> MrzData {
+        let line1 = lines[0];
+        let line2 = lines[1];
+        let line3 = lines[2];
+
+        let (document_number, document_number_check, optional_data) =
+            reader.document_number(&line1[5..14], line1.as_bytes()[14] as char, &line1[15..30]);
+        let (surname, given_names) = reader.name(line3);
+
+        MrzData {
+            document_format: Some(DocumentFormat::TD1),
+            document_type: reader.document_type(&line1[0..2]),
+            issuing_country: reader.code("Issuing country", &line1[2..5]),
+            document_number,
+            surname,
+            given_names,
+            nationality: reader.code("Nationality", &line2[15..18]),
+            date_of_birth: reader.date("Date of birth", &line2[0..6], DateKind::Birth),
+            sex: reader.sex(&line2[7..8]),
+            date_of_expiry: reader.date("Date of expiry", &line2[8..14], DateKind::Expiry),
+            personal_number: None,
+            optional_data,
+            optional_data_2: Self::optional_field(&line2[18..29]),
+            check_digits: CheckDigits {
+                document_number_check,
+                date_of_birth_check: reader.check_digit("Date of birth", line2, 6),
+                date_of_expiry_check: reader.check_digit("Date of expiry", line2, 14),
+                personal_number_check: '<',
+                composite_check: reader.check_digit("Composite", line2, 29),
+            },
+            raw_mrz_lines: lines.iter().map(|l| l.to_string()).collect(),
+            corrections: Vec::new(),
+            unreadable: Vec::new(),
+            repairs: Vec::new(),
+            confidences: Vec::new(),
+        }
+    }
+
+    // Line 1: Positions 1-2 (Document type), 3-5 (Issuing country), 6-36 (Name)
+    // Line 2: Positions 1-9 (Document number), 10 (Check digit), 11-13 (Nationality),
+    // 14-19 (Birth date), 20 (Check digit), 21 (Sex), 22-27 (Expiry date),
+    // 28 (Check digit), 29-35 (Optional data), 36 (Composite check digit)
+    fn parse_td2(lines: &[&str], reader: &mut FieldReader) -> MrzData {
+        let line1 = lines[0];
+        let line2 = lines[1];
+
+        let (document_number, document_number_check, optional_data) =
+            reader.document_number(&line2[0..9], line2.as_bytes()[9] as char, &line2[28..35]);
+        let (surname, given_names) = reader.name(&line1[5..36]);
+
+        MrzData {
+            document_format: Some(DocumentFormat::TD2),
+            document_type: reader.document_type(&line1[0..2]),
+            issuing_country: reader.code("Issuing country", &line1[2..5]),
+            document_number,
+            surname,
+            given_names,
+            nationality: reader.code("Nationality", &line2[10..13]),
+            date_of_birth: reader.date("Date of birth", &line2[13..19], DateKind::Birth),
+            sex: reader.sex(&line2[20..21]),
+            date_of_expiry: reader.date("Date of expiry", &line2[21..27], DateKind::Expiry),
+            personal_number: None,
+            optional_data,
+            optional_data_2: None,
+            check_digits: CheckDigits {
+                document_number_check,
+                date_of_birth_check: reader.check_digit("Date of birth", line2, 19),
+                date_of_expiry_check: reader.check_digit("Date of expiry", line2, 27),
+                personal_number_check: '<',
+                composite_check: reader.check_digit("Composite", line2, 35),
+            },
+            raw_mrz_lines: lines.iter().map(|l| l.to_string()).collect(),
+            corrections: Vec::new(),
+            unreadable: Vec::new(),
+            repairs: Vec::new(),
+            confidences: Vec::new(),
+        }
+    }
+
+    // Line 1: Positions 1-2 (Document type), 3-5 (Issuing country), 6-44 (Name)
+    // Line 2: Positions 1-9 (Document number), 10 (Check digit), 11-13 (Nationality),
+    // 14-19 (Birth date), 20 (Check digit), 21 (Sex), 22-27 (Expiry date),
+    // 28 (Check digit), 29-42 (Personal number), 43 (Check digit), 44 (Composite check digit)
+    fn parse_td3(lines: &[&str], reader: &mut FieldReader) -> MrzData {
+        let line1 = lines[0];
+        let line2 = lines[1];
+
+        let (document_number, document_number_check, _) =
+            reader.document_number(&line2[0..9], line2.as_bytes()[9] as char, "");
+        let (surname, given_names) = reader.name(&line1[5..44]);
+
+        MrzData {
+            document_format: Some(DocumentFormat::TD3),
+            document_type: reader.document_type(&line1[0..2]),
+            issuing_country: reader.code("Issuing country", &line1[2..5]),
+            document_number,
+            surname,
+            given_names,
+            nationality: reader.code("Nationality", &line2[10..13]),
+            date_of_birth: reader.date("Date of birth", &line2[13..19], DateKind::Birth),
+            sex: reader.sex(&line2[20..21]),
+            date_of_expiry: reader.date("Date of expiry", &line2[21..27], DateKind::Expiry),
+            personal_number: Self::optional_field(&line2[28..42]),
+            optional_data: None,
+            optional_data_2: None,
+            check_digits: CheckDigits {
+                document_number_check,
+                date_of_birth_check: reader.check_digit("Date of birth", line2, 19),
+                date_of_expiry_check: reader.check_digit("Date of expiry", line2, 27),
+                personal_number_check: reader.check_digit("Personal number", line2, 42),
+                composite_check: reader.check_digit("Composite", line2, 43),
+            },
+            raw_mrz_lines: lines.iter().map(|l| l.to_string()).collect(),
+            corrections: Vec::new(),
+            unreadable: Vec::new(),
+            repairs: Vec::new(),
+            confidences: Vec::new(),
+        }
+    }
+
+    // Line 1: Positions 1-2 (Document type), 3-5 (Issuing state), 6-44 / 6-36 (Name)
+    // Line 2: Positions 1-9 (Document number), 10 (Check digit), 11-13 (Nationality),
+    // 14-19 (Birth date), 20 (Check digit), 21 (Sex), 22-27 (Expiry date),
+    // 28 (Check digit), 29-44 / 29-36 (Optional data)
+    // Visas have no composite check digit.
+    fn parse_mrv(lines: &[&str], format: DocumentFormat, reader: &mut FieldReader) -> MrzData {
+        let line1 = lines[0];
+        let line2 = lines[1];
+        let line_length = format.mrz_chars_per_line();
+
+        let (document_number, document_number_check, _) =
+            reader.document_number(&line2[0..9], line2.as_bytes()[9] as char, "");
+        let (surname, given_names) = reader.name(&line1[5..line_length]);
+
+        MrzData {
+            document_format: Some(format),
+            document_type: reader.document_type(&line1[0..2]),
+            issuing_country: reader.code("Issuing country", &line1[2..5]),
+            document_number,
+            surname,
+            given_names,
+            nationality: reader.code("Nationality", &line2[10..13]),
+            date_of_birth: reader.date("Date of birth", &line2[13..19], DateKind::Birth),
+            sex: reader.sex(&line2[20..21]),
+            date_of_expiry: reader.date("Date of expiry", &line2[21..27], DateKind::Expiry),
+            personal_number: None,
+            optional_data: Self::optional_field(&line2[28..line_length]),
+            optional_data_2: None,
+            check_digits: CheckDigits {
+                document_number_check,
+                date_of_birth_check: reader.check_digit("Date of birth", line2, 19),
+                date_of_expiry_check: reader.check_digit("Date of expiry", line2, 27),
+                personal_number_check: '<',
+                composite_check: '<',
+            },
+            raw_mrz_lines: lines.iter().map(|l| l.to_string()).collect(),
+            corrections: Vec::new(),
+            unreadable: Vec::new(),
+            repairs: Vec::new(),
+            confidences: Vec::new(),
+        }
+    }
+
+    fn fillers_to_spaces(field: &str) -> String {
+        field
+            .split('<')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn strip_fillers(field: &str) -> String {
+        field.trim_end_matches('<').to_string()
+    }
+
+    /// Optional field value, or None when it only contains fillers
+    fn optional_field(field: &str) -> Option<String> {
+        let value = Self::strip_fillers(field);
+        if value.is_empty() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// Clean alphabetic MRZ field by correcting OCR confusions between digits and letters.
+    pub(crate) fn clean_mrz_alpha_field(field: &str) -> String {
+        field.chars().map(|c| {
+            let c = c.to_ascii_uppercase();
+            match c {
+                '0' => 'O',
+                '1' => 'I',
+                '2' => 'Z',
+                '3' => 'E',
+                '4' => 'A',
+                '5' => 'S',
+                '6' => 'G',
+                '7' => 'T',
+                '8' => 'B',
+                other => other,
+            }
+        }).collect()
+    }
+
+    /// Map a character commonly confused with a digit back to that digit
+    fn clean_mrz_numeric_char(c: char) -> Option<char> {
+        match c {
+            'O' | 'Q' | 'D' | 'C' => Some('0'),
+            'I' | 'L' => Some('1'),
+            'Z' => Some('2'),
+            'E' => Some('3'),
+            'A' => Some('4'),
+            'S' => Some('5'),
+            'G' => Some('6'),
+            'T' => Some('7'),
+            'B' | 'R' => Some('8'),
+            c if c.is_ascii_digit() => Some(c),
+            _ => None,
+        }
+    }
+}
+
+/// Reads individual MRZ fields according to the parse mode, collecting the fields that
+/// could not be read and the repairs made to the others
+struct FieldReader<'a> {
+    options: &'a ParseOptions,
+    unreadable: Vec<UnreadableField>,
+    repairs: Vec<FieldRepair>,
+}
+
+impl<'a> FieldReader<'a> {
+    fn new(options: &'a ParseOptions) -> Self {
+        FieldReader {
+            options,
+            unreadable: Vec::new(),
+            repairs: Vec::new(),
+        }
+    }
+
+    fn is_lenient(&self) -> bool {
+        self.options.mode == ParseMode::Lenient
+    }
+
+    fn mark_unreadable(&mut self, field: &str, reason: String) {
+        self.unreadable.push(UnreadableField {
+            field: field.to_string(),
+            reason,
+        });
+    }
+
+    fn record_repair(&mut self, field: &str, original: &str, repaired: &str, reason: &str) {
+        self.repairs.push(FieldRepair {
+            field: field.to_string(),
+            original: original.to_string(),
+            repaired: repaired.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Alphabetic field in which digits can only be OCR confusions
+    fn alpha(&mut self, field: &str, value: &str) -> Option<String> {
+        if !value.chars().any(|c| c.is_ascii_digit()) {
+            return Some(value.to_string());
+        }
+        if self.is_lenient() {
+            let repaired = MrzParser::clean_mrz_alpha_field(value);
+            self.record_repair(field, value, &repaired, "digits read as the letters they resemble");
+            Some(repaired)
+        } else {
+            self.mark_unreadable(field, format!("digits in alphabetic field '{}'", value));
+            None
+        }
+    }
+
+    /// Document type code (e.g. "P<" or "PD"), None when it is not a Doc 9303 document code
+    fn document_type(&mut self, field: &str) -> Option<DocumentType> {
+        let value = self.alpha("Document type", field)?;
+        let document_type = DocumentType::parse(&value);
+        if document_type.is_none() && !self.is_unreadable("Document type") {
+            let reason = if MrzParser::strip_fillers(&value).is_empty() {
+                "field is empty".to_string()
+            } else {
+                format!("'{}' is not a document code", value)
+            };
+            self.mark_unreadable("Document type", reason);
+        }
+        document_type
+    }
+
+    /// Three-letter country or organization code
+    fn code(&mut self, field: &str, value: &str) -> String {
+        let code = self.alpha(field, value).unwrap_or_default();
+        let code = code.trim_end_matches('<').to_string();
+        if code.is_empty() && !self.is_unreadable(field) {
+            self.mark_unreadable(field, "field is empty".to_string());
+        }
+        code
+    }
+
+    /// Split the name field into primary and secondary identifiers
+    fn name(&mut self, field: &str) -> (String, String) {
+        let cleaned = match self.alpha("Name", field) {
+            Some(cleaned) => cleaned,
+            None => return (String::new(), String::new()),
+        };
+        let (primary, secondary) = cleaned.split_once("<<").unwrap_or((cleaned.as_str(), ""));
+        let primary = MrzParser::fillers_to_spaces(primary);
+        if primary.is_empty() {
+            self.mark_unreadable("Name", "primary identifier is empty".to_string());
+        }
+        (primary, MrzParser::fillers_to_spaces(secondary))
+    }
+
+    /// Sex as printed in the MRZ: M, F or `<` (unspecified)
+    fn sex(&mut self, field: &str) -> Option<Sex> {
+        // Some issuers print X rather than a filler for unspecified
+        let sex = field.chars().next().and_then(Sex::from_mrz);
+        if sex.is_none() {
+            self.mark_unreadable("Sex", format!("'{}' is not a valid sex", field));
+        }
+        sex
+    }
+
+    /// Document number and its check digit, following the ICAO overflow rule for long numbers.
+    ///
+    /// When the check digit position holds a filler, the number continues in the optional
+    /// data field up to the next filler, and the last character before it is the check digit.
+    /// Returns the number, its check digit and whatever optional data remains.
+    fn document_number(
+        &mut self,
+        number_field: &str,
+        check_char: char,
+        optional_data: &str,
+    ) -> (String, char, Option<String>) {
+        let overflows = check_char == '<' && !optional_data.is_empty() && !optional_data.starts_with('<');
+        let (number, check, remaining) = if overflows {
+            let end = optional_data.find('<').unwrap_or(optional_data.len());
+            let overflow = &optional_data[..end];
+            let (tail, check) = overflow.split_at(overflow.len() - 1);
+            (
+                MrzParser::strip_fillers(&format!("{}{}", number_field, tail)),
+                check.chars().next().unwrap_or('<'),
+                MrzParser::optional_field(optional_data[end..].trim_start_matches('<')),
+            )
+        } else {
+            (
+                MrzParser::strip_fillers(number_field),
+                check_char,
+                MrzParser::optional_field(optional_data),
+            )
+        };
+
+        if number.is_empty() {
+            self.mark_unreadable("Document number", "field is empty".to_string());
+        }
+        (number, self.check_char("Document number", check), remaining)
+    }
+
+    /// Check digit at a line position, read as the digit a letter resembles in lenient mode
+    fn check_digit(&mut self, field: &str, line: &str, index: usize) -> char {
+        self.check_char(field, line.as_bytes()[index] as char)
+    }
+
+    fn check_char(&mut self, field: &str, c: char) -> char {
+        if c.is_ascii_digit() || c == '<' || !self.is_lenient() {
+            return c;
+        }
+        match MrzParser::clean_mrz_numeric_char(c) {
+            Some(digit) => {
+                self.record_repair(
+                    &format!("{} check digit", field),
+                    &c.to_string(),
+                    &digit.to_string(),
+                    "letter read as the digit it resembles",
+                );
+                digit
+            }
+            None => c,
+        }
+    }
+
+    /// Parse an MRZ YYMMDD date; `<<` marks an unknown day or month.
+    ///
+    /// In strict mode any other non-digit or out-of-range component makes the date unreadable.
+    /// In lenient mode letters are read as the digits they resemble (anything else as 0) and an
+    /// out-of-range month or day becomes 1, each repair being recorded.
+    fn date(&mut self, field: &str, value: &str, kind: DateKind) -> DocumentDate {
+        let unreadable = DocumentDate::new(None, None, None, value);
+
+        let digits: String = if value.chars().all(|c| c.is_ascii_digit() || c == '<') {
+            value.to_string()
+        } else if self.is_lenient() {
+            let repaired: String = value
+                .chars()
+                .map(|c| if c == '<' { c } else { MrzParser::clean_mrz_numeric_char(c).unwrap_or('0') })
+                .collect();
+            self.record_repair(field, value, &repaired, "non-digits read as the digits they resemble");
+            repaired
+        } else {
+            self.mark_unreadable(field, format!("non-digit characters in '{}'", value));
+            return unreadable;
+        };
+
+        // Some(None) for an unknown component, None when it cannot be read
+        let component = |part: &str| -> Option<Option<u32>> {
+            match part {
+                "<<" => Some(None),
+                _ => part.parse::<u32>().ok().map(Some),
+            }
+        };
+        let (year, mut month, mut day) =
+            match (component(&digits[0..2]), component(&digits[2..4]), component(&digits[4..6])) {
+                (Some(Some(year)), Some(month), Some(day)) => (year, month, day),
+                _ => {
+                    self.mark_unreadable(field, format!("'{}' is not a YYMMDD date", value));
+                    return unreadable;
+                }
+            };
+
+        let month_valid = month.is_none_or(|m| (1..=12).contains(&m));
+        let day_valid = day.is_none_or(|d| (1..=31).contains(&d));
+        if !month_valid || !day_valid {
+            if !self.is_lenient() {
+                self.mark_unreadable(field, format!("month or day out of range in '{}'", value));
+                return unreadable;
+            }
+            if !month_valid {
+                month = Some(1);
+            }
+            if !day_valid {
+                day = Some(1);
+            }
+            let component = |v: Option<u32>| v.map_or("<<".to_string(), |v| format!("{:02}", v));
+            let repaired = format!("{:02}{}{}", year, component(month), component(day));
+            self.record_repair(field, &digits, &repaired, "month or day out of range replaced with 01");
+        }
+
+        let year = match self.options.date_policy.resolve_year(kind, year, month.unwrap_or(1), day.unwrap_or(1)) {
+            Some(year) => year,
+            None => {
+                self.mark_unreadable(field, format!("'{}' is outside the accepted range for {:?} dates", value, kind));
+                return unreadable;
+            }
+        };
+
+        let date = DocumentDate::new(Some(year), month, day, value);
+        if date.is_complete() && date.to_naive_date().is_none() {
+            self.mark_unreadable(field, format!("'{}' is not a calendar date", value));
+            return unreadable;
+        }
+        date
+    }
+
+    fn is_unreadable(&self, field: &str) -> bool {
+        self.unreadable.iter().any(|u| u.field == field)
+    }
+}
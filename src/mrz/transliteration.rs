@@ -0,0 +1,319 @@
+/// Transliteration of VIZ names into the MRZ character set (ICAO Doc 9303 Part 3, section 6).
+///
+/// Several characters have more than one acceptable MRZ rendering (e.g. `Ü` may appear as
+/// `UE`, `U` or `UXX`), so a VIZ name maps to a set of valid MRZ names. The first option of
+/// every character is the recommended one and is what `transliterate` produces.
+pub struct Transliterator;
+
+impl Transliterator {
+    /// Upper bound on the number of renderings returned for a single name
+    const MAX_RENDERINGS: usize = 256;
+
+    const ASCII_LETTERS: [&'static str; 26] = [
+        "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S", "T",
+        "U", "V", "W", "X", "Y", "Z",
+    ];
+
+    /// Recommended MRZ rendering of a name, with fillers between name components
+    pub fn transliterate(name: &str) -> String {
+        Self::units(name).iter().map(|options| options[0]).collect()
+    }
+
+    /// All acceptable MRZ renderings of a name, recommended rendering first
+    pub fn renderings(name: &str) -> Vec<String> {
+        let mut renderings = vec![String::new()];
+        for options in Self::units(name) {
+            let mut next = Vec::new();
+            for prefix in &renderings {
+                for option in &options {
+                    let rendering = format!("{}{}", prefix, option);
+                    if next.len() < Self::MAX_RENDERINGS && !next.contains(&rendering) {
+                        next.push(rendering);
+                    }
+                }
+            }
+            renderings = next;
+        }
+        renderings
+    }
+
+    /// Whether an MRZ name (fillers or spaces between components) is an acceptable
+    /// rendering of a VIZ name
+    pub fn matches(viz_name: &str, mrz_name: &str) -> bool {
+        let mrz = Self::normalize_mrz(mrz_name);
+        Self::match_units(&Self::units(viz_name), &mrz, false)
+    }
+
+    /// Whether an MRZ name field matches the VIZ primary and secondary identifiers.
+    ///
+    /// A name field that is completely filled (no trailing filler) may have been truncated,
+    /// in which case it only needs to be the start of an acceptable rendering.
+    pub fn matches_name_field(primary: &str, secondary: &str, name_field: &str) -> bool {
+        let truncated = !name_field.is_empty() && !name_field.ends_with('<');
+        let mut units = Self::units(primary);
+        let secondary = Self::units(secondary);
+        if !secondary.is_empty() {
+            units.push(vec!["<<"]);
+            units.extend(secondary);
+        }
+        Self::match_units(&units, &Self::normalize_mrz(name_field), truncated)
+    }
+
+    /// Uppercase, turn spaces into fillers and drop trailing fillers
+    fn normalize_mrz(name: &str) -> String {
+        name.trim()
+            .chars()
+            .map(|c| if c.is_whitespace() { '<' } else { c.to_ascii_uppercase() })
+            .collect::<String>()
+            .trim_end_matches('<')
+            .to_string()
+    }
+
+    /// Match rendering options against an MRZ string, trying every option per unit
+    fn match_units(units: &[Vec<&'static str>], mrz: &str, allow_prefix: bool) -> bool {
+        // reachable[j]: the first i units can render mrz[..j]
+        let mut reachable = vec![false; mrz.len() + 1];
+        reachable[0] = true;
+        for options in units {
+            let mut next = vec![false; mrz.len() + 1];
+            for (j, _) in reachable.iter().enumerate().filter(|(_, r)| **r) {
+                for option in options {
+                    let rest = &mrz[j..];
+                    if rest.starts_with(*option) {
+                        next[j + option.len()] = true;
+                    } else if allow_prefix && option.starts_with(rest) {
+                        // Truncation can cut through the middle of a multi-letter rendering
+                        next[mrz.len()] = true;
+                    }
+                }
+            }
+            if allow_prefix && reachable[mrz.len()] {
+                next[mrz.len()] = true;
+            }
+            reachable = next;
+        }
+        reachable[mrz.len()]
+    }
+
+    /// Rendering options for every character of a name, with separators collapsed into a
+    /// single filler and characters without an MRZ rendering dropped
+    fn units(name: &str) -> Vec<Vec<&'static str>> {
+        let mut units: Vec<Vec<&'static str>> = Vec::new();
+        for c in name.chars() {
+            if Self::is_separator(c) {
+                if units.last().is_some_and(|u| u[0] != "<") {
+                    units.push(vec!["<"]);
+                }
+                continue;
+            }
+            if let Some(options) = Self::options(c) {
+                units.push(options.to_vec());
+            }
+        }
+        while units.last().is_some_and(|u| u[0] == "<") {
+            units.pop();
+        }
+        units
+    }
+
+    /// Hyphens, spaces and commas separate name components and become fillers
+    fn is_separator(c: char) -> bool {
+        c.is_whitespace() || matches!(c, '-' | '‐' | ',' | '<')
+    }
+
+    /// Acceptable MRZ renderings of a single VIZ character, recommended first.
+    ///
+    /// Returns None for characters that are omitted, such as apostrophes and Arabic vowel marks.
+    fn options(c: char) -> Option<&'static [&'static str]> {
+        if let Some(options) = Self::latin(c).or_else(|| Self::cyrillic(c)).or_else(|| Self::arabic(c)) {
+            return Some(options);
+        }
+        // Lowercase letters share the rendering of their uppercase form
+        let mut upper = c.to_uppercase();
+        match (upper.next(), upper.next()) {
+            (Some(u), None) if u != c => Self::options(u),
+            _ => None,
+        }
+    }
+
+    /// Latin letters and the Doc 9303 Part 3 table of transliterations for diacritics
+    fn latin(c: char) -> Option<&'static [&'static str]> {
+        if c.is_ascii_uppercase() {
+            let index = (c as u8 - b'A') as usize;
+            return Some(&Self::ASCII_LETTERS[index..index + 1]);
+        }
+        let options: &'static [&'static str] = match c {
+            'Ä' => &["AE", "A", "AXX"],
+            'Å' => &["AA", "A"],
+            'Æ' => &["AE"],
+            'Ö' => &["OE", "O", "OXX"],
+            'Ø' => &["OE", "O"],
+            'Ü' => &["UE", "U", "UXX"],
+            'Ñ' => &["N", "NXX"],
+            'ß' | 'ẞ' => &["SS"],
+            'Þ' => &["TH"],
+            'Œ' => &["OE"],
+            'Ĳ' => &["IJ"],
+            'À' | 'Á' | 'Â' | 'Ã' | 'Ā' | 'Ă' | 'Ą' => &["A"],
+            'Ç' | 'Ć' | 'Ĉ' | 'Ċ' | 'Č' => &["C"],
+            'Ď' | 'Đ' | 'Ð' => &["D"],
+            'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ĕ' | 'Ė' | 'Ę' | 'Ě' => &["E"],
+            'Ĝ' | 'Ğ' | 'Ġ' | 'Ģ' => &["G"],
+            'Ĥ' | 'Ħ' => &["H"],
+            'Ì' | 'Í' | 'Î' | 'Ï' | 'Ĩ' | 'Ī' | 'Ĭ' | 'Į' | 'İ' | 'ı' => &["I"],
+            'Ĵ' => &["J"],
+            'Ķ' | 'ĸ' => &["K"],
+            'Ĺ' | 'Ļ' | 'Ľ' | 'Ŀ' | 'Ł' => &["L"],
+            'Ń' | 'Ņ' | 'Ň' | 'Ŋ' => &["N"],
+            'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ō' | 'Ŏ' | 'Ő' => &["O"],
+            'Ŕ' | 'Ŗ' | 'Ř' => &["R"],
+            'Ś' | 'Ŝ' | 'Ş' | 'Ș' | 'Š' => &["S"],
+            'Ţ' | 'Ț' | 'Ť' | 'Ŧ' => &["T"],
+            'Ù' | 'Ú' | 'Û' | 'Ũ' | 'Ū' | 'Ŭ' | 'Ů' | 'Ű' | 'Ų' => &["U"],
+            'Ŵ' => &["W"],
+            'Ý' | 'Ÿ' | 'Ŷ' => &["Y"],
+            'Ź' | 'Ż' | 'Ž' => &["Z"],
+            _ => return None,
+        };
+        Some(options)
+    }
+
+    /// Cyrillic transliteration (Doc 9303 Part 3, Table B)
+    fn cyrillic(c: char) -> Option<&'static [&'static str]> {
+        let options: &'static [&'static str] = match c {
+            'А' => &["A"],
+            'Б' => &["B"],
+            'В' => &["V"],
+            'Г' => &["G", "H"],
+            'Ґ' => &["G"],
+            'Д' => &["D"],
+            'Ѓ' => &["G"],
+            'Ђ' => &["D"],
+            'Е' => &["E"],
+            'Ё' => &["E"],
+            'Є' => &["IE"],
+            'Ж' => &["ZH"],
+            'З' => &["Z"],
+            'Ѕ' => &["DZ"],
+            'И' => &["I"],
+            'І' => &["I"],
+            'Ї' => &["I"],
+            'Й' => &["I"],
+            'Ј' => &["J"],
+            'К' => &["K"],
+            'Л' => &["L"],
+            'Љ' => &["LJ"],
+            'М' => &["M"],
+            'Н' => &["N"],
+            'Њ' => &["NJ"],
+            'О' => &["O"],
+            'П' => &["P"],
+            'Р' => &["R"],
+            'С' => &["S"],
+            'Т' => &["T"],
+            'Ћ' => &["C"],
+            'Ќ' => &["K"],
+            'У' => &["U"],
+            'Ў' => &["U"],
+            'Ф' => &["F"],
+            'Х' => &["KH"],
+            'Ц' => &["TS"],
+            'Ч' => &["CH"],
+            'Џ' => &["DZ"],
+            'Ш' => &["SH"],
+            'Щ' => &["SHCH"],
+            'Ъ' => &["IE"],
+            'Ы' => &["Y"],
+            'Ь' => &[""],
+            'Э' => &["E"],
+            'Ю' => &["IU"],
+            'Я' => &["IA"],
+            _ => return None,
+        };
+        Some(options)
+    }
+
+    /// Arabic transliteration (Doc 9303 Part 3, Table C); vowel marks are omitted
+    fn arabic(c: char) -> Option<&'static [&'static str]> {
+        let options: &'static [&'static str] = match c {
+            'ء' => &["XE"],
+            'آ' => &["XAA"],
+            'أ' => &["XAE"],
+            'ؤ' => &["U"],
+            'إ' => &["I"],
+            'ئ' => &["XI"],
+            'ا' => &["A"],
+            'ب' => &["B"],
+            'ة' => &["XTA"],
+            'ت' => &["T"],
+            'ث' => &["XTH"],
+            'ج' => &["J"],
+            'ح' => &["XH"],
+            'خ' => &["XKH"],
+            'د' => &["D"],
+            'ذ' => &["XDH"],
+            'ر' => &["R"],
+            'ز' => &["Z"],
+            'س' => &["S"],
+            'ش' => &["XSH"],
+            'ص' => &["XSS"],
+            'ض' => &["XDZ"],
+            'ط' => &["XTT"],
+            'ظ' => &["XZZ"],
+            'ع' => &["E"],
+            'غ' => &["G"],
+            'ف' => &["F"],
+            'ق' => &["Q"],
+            'ك' => &["K"],
+            'ل' => &["L"],
+            'م' => &["M"],
+            'ن' => &["N"],
+            'ه' => &["H"],
+            'و' => &["W"],
+            'ى' => &["XAY"],
+            'ي' => &["Y"],
+            _ => return None,
+        };
+        Some(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transliterates_with_the_recommended_rendering() {
+        assert_eq!(Transliterator::transliterate("Müller"), "MUELLER");
+        assert_eq!(Transliterator::transliterate("Anna Maria"), "ANNA<MARIA");
+        assert_eq!(Transliterator::renderings("Müller"), vec!["MUELLER", "MULLER", "MUXXLLER"]);
+    }
+
+    #[test]
+    fn matches_every_acceptable_rendering() {
+        assert!(Transliterator::matches("MÜLLER", "MUELLER"));
+        assert!(Transliterator::matches("MÜLLER", "MULLER"));
+        assert!(Transliterator::matches("Müller", "muller"));
+        assert!(!Transliterator::matches("MÜLLER", "MILLER"));
+        assert!(!Transliterator::matches("MÜLLER", "MUELLERS"));
+    }
+
+    #[test]
+    fn matches_name_fields_with_both_identifiers() {
+        assert!(Transliterator::matches_name_field("Müller", "Hans", "MUELLER<<HANS<<<<<<<<"));
+        assert!(Transliterator::matches_name_field("Müller", "Hans", "MULLER<<HANS<<<<<<<<<"));
+        assert!(!Transliterator::matches_name_field("Müller", "Hans", "MUELLER<<HANSI<<<<<<<"));
+    }
+
+    #[test]
+    fn accepts_a_prefix_only_for_truncated_name_fields() {
+        // A completely filled field may stop mid-name
+        assert!(Transliterator::matches_name_field(
+            "Nilavadhanananda",
+            "Chayapa Dejthamrong Lukkunaprasit",
+            "NILAVADHANANANDA<<CHAYAPA<DEJTHAMRONG<L"
+        ));
+        // A field ending in fillers holds the whole name
+        assert!(!Transliterator::matches_name_field("Eriksson", "Anna Maria", "ERIKSSON<<ANNA<<<<<<<<"));
+    }
+}
@@ -0,0 +1,98 @@
+use image::imageops::{self, FilterType};
+use image::{GrayImage, Luma};
+use imageproc::contrast::otsu_level;
+use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
+
+/// A grayscale image rotated so its text lines are horizontal
+#[derive(Debug, Clone)]
+pub struct DeskewedImage {
+    pub image: GrayImage,
+    /// Skew detected in the input, in degrees (positive when lines fall to the right);
+    /// the image was rotated by the opposite angle
+    pub angle: f32,
+}
+
+/// Estimates and corrects the skew of document images with a projection profile.
+///
+/// Dark pixels are projected onto rows along every candidate angle. When the angle matches the
+/// skew, the MRZ and other text lines fall into few rows separated by empty ones, which
+/// maximizes the sum of squared row counts.
+pub struct Deskewer;
+
+impl Deskewer {
+    /// Largest skew searched for, in degrees
+    const MAX_ANGLE: f32 = 15.0;
+    const COARSE_STEP: f32 = 0.5;
+    const FINE_STEP: f32 = 0.05;
+    /// Skews below this are left alone, since rotating blurs the characters
+    const MIN_CORRECTION: f32 = 0.1;
+    /// Images are analyzed at this width at most
+    const ANALYSIS_WIDTH: u32 = 1000;
+    /// Dark pixels projected at most, sampled evenly beyond that
+    const MAX_SAMPLES: usize = 60_000;
+
+    /// Detect the skew of the image and rotate it back
+    pub fn deskew(img: &GrayImage) -> DeskewedImage {
+        let angle = Self::estimate_angle(img);
+        if angle.abs() < Self::MIN_CORRECTION {
+            return DeskewedImage { image: img.clone(), angle };
+        }
+        // Fill the uncovered corners with white paper rather than black, which OCR reads as ink
+        let image = rotate_about_center(img, -angle.to_radians(), Interpolation::Bilinear, Luma([255u8]));
+        DeskewedImage { image, angle }
+    }
+
+    /// Skew of the text lines in degrees, positive when they fall to the right
+    pub fn estimate_angle(img: &GrayImage) -> f32 {
+        let points = Self::ink_points(img);
+        if points.is_empty() {
+            return 0.0;
+        }
+
+        let search = |from: f32, to: f32, step: f32| {
+            let steps = ((to - from) / step).round() as i32;
+            (0..=steps)
+                .map(|i| from + i as f32 * step)
+                .map(|angle| (angle, Self::profile_score(&points, angle)))
+                .fold((0.0, f64::MIN), |best, candidate| if candidate.1 > best.1 { candidate } else { best })
+                .0
+        };
+        let coarse = search(-Self::MAX_ANGLE, Self::MAX_ANGLE, Self::COARSE_STEP);
+        search(coarse - Self::COARSE_STEP, coarse + Self::COARSE_STEP, Self::FINE_STEP)
+    }
+
+    /// Coordinates of dark pixels (at or below the Otsu level) of a downscaled copy of the image
+    fn ink_points(img: &GrayImage) -> Vec<(f32, f32)> {
+        let small;
+        let img = if img.width() > Self::ANALYSIS_WIDTH {
+            let height = (img.height() as u64 * Self::ANALYSIS_WIDTH as u64 / img.width() as u64).max(1) as u32;
+            small = imageops::resize(img, Self::ANALYSIS_WIDTH, height, FilterType::Triangle);
+            &small
+        } else {
+            img
+        };
+
+        let threshold = otsu_level(img);
+        let points: Vec<(f32, f32)> = img
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[0] <= threshold)
+            .map(|(x, y, _)| (x as f32, y as f32))
+            .collect();
+        let stride = points.len().div_ceil(Self::MAX_SAMPLES).max(1);
+        points.into_iter().step_by(stride).collect()
+    }
+
+    /// Sum of squared row counts of the points projected along the angle
+    fn profile_score(points: &[(f32, f32)], angle: f32) -> f64 {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let rows: Vec<i64> = points.iter().map(|(x, y)| (y * cos - x * sin).round() as i64).collect();
+        let (Some(min), Some(max)) = (rows.iter().min(), rows.iter().max()) else {
+            return 0.0;
+        };
+        let mut counts = vec![0u64; (max - min + 1) as usize];
+        for row in &rows {
+            counts[(row - min) as usize] += 1;
+        }
+        counts.iter().map(|c| (c * c) as f64).sum()
+    }
+}
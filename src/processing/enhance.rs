@@ -0,0 +1,285 @@
+use image::imageops::{self, FilterType};
+use image::{GrayImage, ImageBuffer, Luma};
+use imageproc::contrast::otsu_level;
+use imageproc::filter::gaussian_blur_f32;
+
+use crate::processing::ImageProcessor;
+
+/// How a grayscale image is turned into black text on white before OCR
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Binarization {
+    /// Keep the gray levels and let Tesseract binarize
+    #[default]
+    None,
+    /// One global threshold; suits evenly lit flatbed scans
+    Otsu,
+    /// Local threshold `mean * (1 + k * (deviation / 128 - 1))` over a window (in pixels) around
+    /// every pixel; keeps faint characters in shadows without turning the background into noise
+    Sauvola { window: u32, k: f32 },
+    /// Local threshold `mean + k * deviation`; k is usually negative (around -0.2)
+    Niblack { window: u32, k: f32 },
+}
+
+/// Contrast limited adaptive histogram equalization
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clahe {
+    /// The image is split into `tiles` x `tiles` regions, each equalized on its own
+    pub tiles: u32,
+    /// Histogram bins are clipped at this multiple of the mean bin count, which limits how much
+    /// noise in flat regions is amplified
+    pub clip_limit: f32,
+}
+
+impl Default for Clahe {
+    fn default() -> Self {
+        Clahe {
+            tiles: 8,
+            clip_limit: 2.0,
+        }
+    }
+}
+
+/// Preprocessing applied to an image before one OCR stage, in the order of the fields
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EnhanceOptions {
+    /// Divide out uneven lighting (shadows, vignetting, phone flash falloff)
+    pub flatten_background: bool,
+    pub clahe: Option<Clahe>,
+    pub binarization: Binarization,
+}
+
+impl EnhanceOptions {
+    /// Defaults for the MRZ: flat background and Sauvola binarization, since the MRZ is black
+    /// OCR-B text on a plain background and suffers most from shadows and laminate glare
+    pub fn mrz() -> Self {
+        EnhanceOptions {
+            flatten_background: true,
+            clahe: None,
+            binarization: Binarization::Sauvola { window: 51, k: 0.2 },
+        }
+    }
+
+    /// Defaults for the VIZ: flat background and CLAHE but no binarization, since guilloche
+    /// backgrounds and colored text binarize poorly
+    pub fn viz() -> Self {
+        EnhanceOptions {
+            flatten_background: true,
+            clahe: Some(Clahe::default()),
+            binarization: Binarization::None,
+        }
+    }
+}
+
+/// Preprocessing for every OCR stage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreprocessOptions {
+    pub mrz: EnhanceOptions,
+    pub viz: EnhanceOptions,
+}
+
+impl Default for PreprocessOptions {
+    fn default() -> Self {
+        PreprocessOptions {
+            mrz: EnhanceOptions::mrz(),
+            viz: EnhanceOptions::viz(),
+        }
+    }
+}
+
+/// Illumination correction, contrast enhancement and binarization of grayscale images
+pub struct ImageEnhancer;
+
+impl ImageEnhancer {
+    /// The background is estimated at this fraction of the image size
+    const BACKGROUND_SCALE: u32 = 4;
+
+    /// Apply the preprocessing steps that are enabled in the options
+    pub fn enhance(img: &GrayImage, options: &EnhanceOptions) -> GrayImage {
+        let mut enhanced = if options.flatten_background {
+            Self::flatten_background(img)
+        } else {
+            img.clone()
+        };
+        if let Some(clahe) = &options.clahe {
+            enhanced = Self::clahe(&enhanced, clahe);
+        }
+        match options.binarization {
+            Binarization::None => enhanced,
+            Binarization::Otsu => Self::otsu(&enhanced),
+            Binarization::Sauvola { window, k } => Self::sauvola(&enhanced, window, k),
+            Binarization::Niblack { window, k } => Self::niblack(&enhanced, window, k),
+        }
+    }
+
+    /// Divide the image by an estimate of its background, so paper reads as white everywhere.
+    ///
+    /// The background is the local maximum over a window wider than any character stroke,
+    /// smoothed, computed on a downscaled copy for speed.
+    pub fn flatten_background(img: &GrayImage) -> GrayImage {
+        let (width, height) = img.dimensions();
+        if width == 0 || height == 0 {
+            return img.clone();
+        }
+        let small_width = (width / Self::BACKGROUND_SCALE).max(1);
+        let small_height = (height / Self::BACKGROUND_SCALE).max(1);
+        let small = imageops::resize(img, small_width, small_height, FilterType::Triangle);
+        let radius = (small_width.max(small_height) / 60).max(2);
+        let background = gaussian_blur_f32(&ImageProcessor::max_filter(&small, radius, radius), radius as f32);
+        let background = imageops::resize(&background, width, height, FilterType::Triangle);
+
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let value = img.get_pixel(x, y)[0] as u32;
+            let paper = (background.get_pixel(x, y)[0] as u32).max(1);
+            Luma([(value * 255 / paper).min(255) as u8])
+        })
+    }
+
+    /// Equalize the histogram of every tile, limiting the contrast gain, and interpolate
+    /// between the mappings of neighbouring tiles so tile borders don't show
+    pub fn clahe(img: &GrayImage, options: &Clahe) -> GrayImage {
+        let (width, height) = img.dimensions();
+        let tiles_x = options.tiles.clamp(1, width.max(1));
+        let tiles_y = options.tiles.clamp(1, height.max(1));
+        let tile_width = width.div_ceil(tiles_x).max(1);
+        let tile_height = height.div_ceil(tiles_y).max(1);
+
+        let mut mappings = Vec::with_capacity((tiles_x * tiles_y) as usize);
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                let mut histogram = [0u32; 256];
+                let mut pixels = 0u32;
+                for y in (ty * tile_height)..((ty + 1) * tile_height).min(height) {
+                    for x in (tx * tile_width)..((tx + 1) * tile_width).min(width) {
+                        histogram[img.get_pixel(x, y)[0] as usize] += 1;
+                        pixels += 1;
+                    }
+                }
+                mappings.push(Self::clipped_mapping(&mut histogram, pixels, options.clip_limit));
+            }
+        }
+
+        // Tile centers are the interpolation nodes
+        let node = |position: u32, tile_size: u32, tiles: u32| {
+            let t = (position as f32 + 0.5) / tile_size as f32 - 0.5;
+            let t = t.clamp(0.0, (tiles - 1) as f32);
+            let first = t.floor() as u32;
+            let second = (first + 1).min(tiles - 1);
+            (first, second, t - first as f32)
+        };
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let value = img.get_pixel(x, y)[0] as usize;
+            let (x0, x1, fx) = node(x, tile_width, tiles_x);
+            let (y0, y1, fy) = node(y, tile_height, tiles_y);
+            let map = |tx: u32, ty: u32| mappings[(ty * tiles_x + tx) as usize][value] as f32;
+            let top = map(x0, y0) * (1.0 - fx) + map(x1, y0) * fx;
+            let bottom = map(x0, y1) * (1.0 - fx) + map(x1, y1) * fx;
+            Luma([(top * (1.0 - fy) + bottom * fy).round().clamp(0.0, 255.0) as u8])
+        })
+    }
+
+    /// Threshold the whole image at its Otsu level
+    pub fn otsu(img: &GrayImage) -> GrayImage {
+        let level = otsu_level(img);
+        Self::threshold(img, |_, _, value| value > level)
+    }
+
+    /// Sauvola thresholding over a `window` x `window` neighbourhood
+    pub fn sauvola(img: &GrayImage, window: u32, k: f32) -> GrayImage {
+        let stats = LocalStatistics::new(img, window);
+        Self::threshold(img, |x, y, value| {
+            let (mean, deviation) = stats.at(x, y);
+            value as f64 > mean * (1.0 + k as f64 * (deviation / 128.0 - 1.0))
+        })
+    }
+
+    /// Niblack thresholding over a `window` x `window` neighbourhood
+    pub fn niblack(img: &GrayImage, window: u32, k: f32) -> GrayImage {
+        let stats = LocalStatistics::new(img, window);
+        Self::threshold(img, |x, y, value| {
+            let (mean, deviation) = stats.at(x, y);
+            value as f64 > mean + k as f64 * deviation
+        })
+    }
+
+    /// White where `is_background` holds, black elsewhere
+    fn threshold(img: &GrayImage, is_background: impl Fn(u32, u32, u8) -> bool) -> GrayImage {
+        ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+            Luma([if is_background(x, y, img.get_pixel(x, y)[0]) { 255 } else { 0 }])
+        })
+    }
+
+    /// Gray level mapping of one CLAHE tile: the histogram is clipped, the excess spread over
+    /// all bins, and the cumulative histogram scaled to 0..255
+    fn clipped_mapping(histogram: &mut [u32; 256], pixels: u32, clip_limit: f32) -> [u8; 256] {
+        let limit = ((clip_limit * pixels as f32 / 256.0) as u32).max(1);
+        let mut excess = 0;
+        for bin in histogram.iter_mut() {
+            if *bin > limit {
+                excess += *bin - limit;
+                *bin = limit;
+            }
+        }
+        for (index, bin) in histogram.iter_mut().enumerate() {
+            *bin += excess / 256 + u32::from((index as u32) < excess % 256);
+        }
+
+        let mut mapping = [0u8; 256];
+        let mut cumulative = 0u64;
+        for (value, bin) in histogram.iter().enumerate() {
+            cumulative += *bin as u64;
+            mapping[value] = (cumulative * 255 / pixels.max(1) as u64).min(255) as u8;
+        }
+        mapping
+    }
+}
+
+/// Mean and standard deviation of the gray levels around every pixel, from integral images
+struct LocalStatistics {
+    width: u32,
+    height: u32,
+    radius: u32,
+    sums: Vec<u64>,
+    squares: Vec<u64>,
+}
+
+impl LocalStatistics {
+    fn new(img: &GrayImage, window: u32) -> Self {
+        let (width, height) = img.dimensions();
+        let stride = width as usize + 1;
+        let mut sums = vec![0u64; stride * (height as usize + 1)];
+        let mut squares = vec![0u64; stride * (height as usize + 1)];
+        for y in 0..height as usize {
+            let mut row_sum = 0u64;
+            let mut row_squares = 0u64;
+            for x in 0..width as usize {
+                let value = img.get_pixel(x as u32, y as u32)[0] as u64;
+                row_sum += value;
+                row_squares += value * value;
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row_sum;
+                squares[(y + 1) * stride + x + 1] = squares[y * stride + x + 1] + row_squares;
+            }
+        }
+        LocalStatistics {
+            width,
+            height,
+            radius: window / 2,
+            sums,
+            squares,
+        }
+    }
+
+    fn at(&self, x: u32, y: u32) -> (f64, f64) {
+        let stride = self.width as usize + 1;
+        let x0 = x.saturating_sub(self.radius) as usize;
+        let y0 = y.saturating_sub(self.radius) as usize;
+        let x1 = (x + self.radius + 1).min(self.width) as usize;
+        let y1 = (y + self.radius + 1).min(self.height) as usize;
+        let area = |table: &[u64]| {
+            (table[y1 * stride + x1] + table[y0 * stride + x0] - table[y0 * stride + x1] - table[y1 * stride + x0]) as f64
+        };
+        let count = ((x1 - x0) * (y1 - y0)) as f64;
+        let mean = area(&self.sums) / count;
+        let variance = (area(&self.squares) / count - mean * mean).max(0.0);
+        (mean, variance.sqrt())
+    }
+}
@@ -0,0 +1,178 @@
+use regex::Regex;
+use std::ops::Range;
+
+use crate::models::FieldConfidence;
+
+/// A line of OCR text with the confidence of every character (0 to 100).
+///
+/// Words are separated by single spaces, which have no confidence.
+#[derive(Debug, Clone, Default)]
+pub struct OcrLine {
+    pub text: String,
+    pub confidences: Vec<Option<f32>>,
+}
+
+impl OcrLine {
+    /// Characters other than whitespace with their confidence
+    pub fn characters(&self) -> Vec<(char, f32)> {
+        self.text
+            .chars()
+            .zip(&self.confidences)
+            .filter_map(|(c, confidence)| confidence.map(|confidence| (c, confidence)))
+            .filter(|(c, _)| !c.is_whitespace())
+            .collect()
+    }
+
+    /// Confidence of the text in a byte range of the line (e.g. a regex capture)
+    pub fn confidence(&self, field: &str, range: Range<usize>) -> FieldConfidence {
+        let start = self.text[..range.start].chars().count();
+        let length = self.text[range].chars().count();
+        let characters: Vec<(char, f32)> = self
+            .text
+            .chars()
+            .zip(&self.confidences)
+            .skip(start)
+            .take(length)
+            .filter_map(|(c, confidence)| confidence.map(|confidence| (c, confidence)))
+            .filter(|(c, _)| !c.is_whitespace())
+            .collect();
+        FieldConfidence::new(field, &characters)
+    }
+}
+
+/// Reads lines and per-character confidences from Tesseract hOCR output.
+///
+/// Character confidences (`x_conf`) are only present when the `hocr_char_boxes` variable is
+/// set; otherwise every character of a word gets the word confidence (`x_wconf`).
+pub struct HocrParser;
+
+impl HocrParser {
+    pub fn parse(hocr: &str) -> Vec<OcrLine> {
+        let element = Regex::new(r#"<span class=['"](\w+)['"][^>]*?title=['"]([^'"]*)['"][^>]*>([^<]*)"#).unwrap();
+        let mut lines = Vec::new();
+        let mut line: Option<OcrLine> = None;
+        // Text and confidence of the current word until its characters are known
+        let mut word: Option<(String, f32)> = None;
+        let mut word_has_characters = false;
+
+        for cap in element.captures_iter(hocr) {
+            let class = &cap[1];
+            let title = &cap[2];
+            match class {
+                "ocr_line" | "ocr_caption" | "ocr_header" | "ocr_textfloat" => {
+                    Self::finish_word(&mut line, word.take(), word_has_characters);
+                    lines.extend(line.take());
+                    line = Some(OcrLine::default());
+                }
+                "ocrx_word" => {
+                    Self::finish_word(&mut line, word.take(), word_has_characters);
+                    let line = line.get_or_insert_with(OcrLine::default);
+                    if !line.text.is_empty() {
+                        line.text.push(' ');
+                        line.confidences.push(None);
+                    }
+                    let confidence = Self::title_value(title, "x_wconf").unwrap_or(0.0);
+                    word = Some((Self::unescape(cap[3].trim()), confidence));
+                    word_has_characters = false;
+                }
+                "ocrx_cinfo" => {
+                    word_has_characters = true;
+                    let confidence = Self::title_value(title, "x_conf").unwrap_or(0.0);
+                    let line = line.get_or_insert_with(OcrLine::default);
+                    for c in Self::unescape(&cap[3]).chars() {
+                        line.text.push(c);
+                        line.confidences.push(Some(confidence));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Self::finish_word(&mut line, word, word_has_characters);
+        lines.extend(line);
+        lines
+    }
+
+    /// Text of the lines, one per line
+    pub fn text(lines: &[OcrLine]) -> String {
+        lines.iter().map(|l| l.text.as_str()).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Add a word without character confidences, giving each character the word confidence
+    fn finish_word(line: &mut Option<OcrLine>, word: Option<(String, f32)>, has_characters: bool) {
+        if let (Some(line), Some((text, confidence)), false) = (line.as_mut(), word, has_characters) {
+            for c in text.chars() {
+                line.text.push(c);
+                line.confidences.push(Some(confidence));
+            }
+        }
+    }
+
+    /// Numeric value of a property in an hOCR title, e.g. `x_wconf 96` or `x_conf 98.7`
+    fn title_value(title: &str, property: &str) -> Option<f32> {
+        title.split(';').find_map(|part| {
+            let mut tokens = part.split_whitespace();
+            if tokens.next() == Some(property) {
+                tokens.next()?.parse().ok()
+            } else {
+                None
+            }
+        })
+    }
+
+    fn unescape(text: &str) -> String {
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&#39;", "'")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHARACTER_HOCR: &str = "<div class='ocr_page'>\
+        <span class='ocr_line' id='line_1' title=\"bbox 10 10 200 30; baseline 0 0\">\
+        <span class='ocrx_word' id='word_1' title='bbox 10 10 80 30; x_wconf 91'>\
+        <span class='ocrx_cinfo' title='x_bboxes 10 10 20 30; x_conf 98.5'>P</span>\
+        <span class='ocrx_cinfo' title='x_bboxes 20 10 30 30; x_conf 40'>&lt;</span>\
+        <span class='ocrx_cinfo' title='x_bboxes 30 10 40 30; x_conf 97'>U</span>\
+        </span>\
+        <span class='ocrx_word' id='word_2' title='bbox 90 10 120 30; x_wconf 88'>\
+        <span class='ocrx_cinfo' title='x_bboxes 90 10 100 30; x_conf 88'>T</span>\
+        </span></span>\
+        <span class='ocr_line' id='line_2' title=\"bbox 10 40 200 60\">\
+        <span class='ocrx_word' id='word_3' title='bbox 10 40 80 60; x_wconf 75'>L898</span>\
+        </span></div>";
+
+    #[test]
+    fn parses_character_confidences() {
+        let lines = HocrParser::parse(CHARACTER_HOCR);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text, "P<U T");
+        assert_eq!(lines[0].confidences, vec![Some(98.5), Some(40.0), Some(97.0), None, Some(88.0)]);
+        assert_eq!(lines[0].characters(), vec![('P', 98.5), ('<', 40.0), ('U', 97.0), ('T', 88.0)]);
+        assert_eq!(HocrParser::text(&lines), "P<U T\nL898");
+    }
+
+    #[test]
+    fn falls_back_to_word_confidences() {
+        let lines = HocrParser::parse(CHARACTER_HOCR);
+        assert_eq!(lines[1].characters(), vec![('L', 75.0), ('8', 75.0), ('9', 75.0), ('8', 75.0)]);
+    }
+
+    #[test]
+    fn reports_field_confidence_over_a_range() {
+        let lines = HocrParser::parse(CHARACTER_HOCR);
+        let confidence = lines[0].confidence("Document code", 0..2);
+        assert_eq!(confidence.confidence, 69.25);
+        assert_eq!(confidence.uncertain.len(), 1);
+        assert_eq!((confidence.uncertain[0].position, confidence.uncertain[0].character), (1, '<'));
+
+        // Spaces between words carry no confidence
+        let confidence = lines[0].confidence("Words", 2..5);
+        assert_eq!(confidence.confidence, 92.5);
+    }
+}
@@ -1,63 +1,372 @@
 use crate::utils::PassportError;
-use image::{DynamicImage, ImageBuffer, Luma};
-use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
+use image::imageops::{self, FilterType};
+use image::{ColorType, DynamicImage, GrayImage, ImageBuffer, Luma, RgbImage, RgbaImage};
+use imageproc::contrast::otsu_level;
+use imageproc::gradients::horizontal_sobel;
+use imageproc::region_labelling::{connected_components, Connectivity};
+use crate::models::DocumentFormat;
+use crate::processing::{
+    DeskewedImage, Deskewer, DocumentQuad, ImageQuality, NormalizedPage, OrientationDetector, OrientedImage,
+    PageNormalizer, QualityAnalyzer, QualityThresholds,
+};
+use std::collections::HashMap;
 use std::path::Path;
-use std::path::PathBuf;
-use std::io::Write;
+
+/// A rectangle of pixels in an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The MRZ band of a document image
+#[derive(Debug, Clone)]
+pub struct MrzRegion {
+    /// Bounds of the band in the input image
+    pub bounds: BoundingBox,
+    /// The band cropped from the input and upscaled for OCR
+    pub image: GrayImage,
+}
+
+/// Pixel layout of an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorModel {
+    Gray,
+    Rgb,
+    Rgba,
+}
+
+impl ColorModel {
+    pub fn bytes_per_pixel(&self) -> u32 {
+        match self {
+            ColorModel::Gray => 1,
+            ColorModel::Rgb => 3,
+            ColorModel::Rgba => 4,
+        }
+    }
+}
+
+/// A step of the processing pipeline that changed the image
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageTransform {
+    /// Converted to grayscale from another color model
+    Grayscale { from: ColorModel },
+    /// The document found at `quad` warped to the dimensions of `format`
+    PerspectiveWarp {
+        quad: DocumentQuad,
+        format: DocumentFormat,
+        pixels_per_mm: f32,
+    },
+    /// Turned clockwise by 90, 180 or 270 degrees to read upright with the MRZ at the bottom
+    Rotate { degrees: u32 },
+    /// Rotated back by the skew that was detected, in degrees
+    Deskew { angle: f32 },
+}
+
+/// An image ready for OCR, whose pixels are handed to Tesseract as they are
+#[derive(Debug, Clone)]
+pub struct ProcessedImage {
+    /// Rows of `width` pixels from top to bottom, without padding
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub color_model: ColorModel,
+    /// Resolution of the image: measured on the document when the page was normalized,
+    /// otherwise as recorded in the image file, if at all
+    pub dpi: Option<u32>,
+    /// Transforms applied to the input, in order
+    pub transforms: Vec<ImageTransform>,
+}
+
+impl ProcessedImage {
+    pub fn from_gray(image: GrayImage, dpi: Option<u32>, transforms: Vec<ImageTransform>) -> Self {
+        ProcessedImage {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.into_raw(),
+            color_model: ColorModel::Gray,
+            dpi,
+            transforms,
+        }
+    }
+
+    /// Physical scale of the image, when its resolution is known
+    pub fn pixels_per_mm(&self) -> Option<f32> {
+        self.dpi.map(|dpi| dpi as f32 / 25.4)
+    }
+
+    pub fn bytes_per_line(&self) -> u32 {
+        self.width * self.color_model.bytes_per_pixel()
+    }
+
+    /// A grayscale copy of the image
+    pub fn to_gray(&self) -> Result<GrayImage, PassportError> {
+        let mismatch = || {
+            PassportError::ImageProcessingError(format!(
+                "Pixel buffer of {} bytes does not match a {}x{} {:?} image",
+                self.pixels.len(),
+                self.width,
+                self.height,
+                self.color_model
+            ))
+        };
+        let pixels = self.pixels.clone();
+        match self.color_model {
+            ColorModel::Gray => GrayImage::from_raw(self.width, self.height, pixels).ok_or_else(mismatch),
+            ColorModel::Rgb => RgbImage::from_raw(self.width, self.height, pixels)
+                .map(|img| DynamicImage::ImageRgb8(img).to_luma8())
+                .ok_or_else(mismatch),
+            ColorModel::Rgba => RgbaImage::from_raw(self.width, self.height, pixels)
+                .map(|img| DynamicImage::ImageRgba8(img).to_luma8())
+                .ok_or_else(mismatch),
+        }
+    }
+}
 
 pub struct ImageProcessor;
 
 impl ImageProcessor {
-    pub fn process_image(image_path: &Path) -> Result<Vec<u8>, PassportError> {
-        let img = image::open(image_path)
+    /// Images are searched for the MRZ at this width
+    const MRZ_ANALYSIS_WIDTH: u32 = 600;
+    /// The MRZ band spans at least this share of the image width
+    const MRZ_MIN_WIDTH_RATIO: f32 = 0.6;
+    /// Minimum width to height ratio of the MRZ band (TD1 has the tallest, at about 5:1)
+    const MRZ_MIN_ASPECT_RATIO: f32 = 4.0;
+    /// Cropped MRZ bands narrower than this are upscaled, giving OCR-B characters about 35 pixels
+    const MRZ_OCR_WIDTH: u32 = 1600;
+
+    pub fn process_image(image_path: &Path) -> Result<ProcessedImage, PassportError> {
+        let data = std::fs::read(image_path)
             .map_err(|e| PassportError::ImageProcessingError(format!("Failed to open image: {}", e)))?;
-        let processed = Self::preprocess_image(&img);
-        Ok(processed)
+        Self::process_bytes(&data)
+    }
+
+    /// Process an encoded image (JPEG, PNG, ...) held in memory
+    pub fn process_bytes(data: &[u8]) -> Result<ProcessedImage, PassportError> {
+        let img = image::load_from_memory(data)
+            .map_err(|e| PassportError::ImageProcessingError(format!("Failed to decode image: {}", e)))?;
+        Ok(Self::process_dynamic_image(&img, Self::read_dpi(data)))
     }
 
-    fn preprocess_image(img: &DynamicImage) -> Vec<u8> {
+    /// Process a decoded image, e.g. a camera frame; `dpi` is its resolution if known
+    pub fn process_dynamic_image(img: &DynamicImage, dpi: Option<u32>) -> ProcessedImage {
+        let mut transforms = Vec::new();
+
         // Convert to grayscale
-        let gray = img.to_luma8();
+        let color_model = Self::color_model(img.color());
+        if color_model != ColorModel::Gray {
+            transforms.push(ImageTransform::Grayscale { from: color_model });
+        }
+        let mut gray = img.to_luma8();
+        let mut dpi = dpi;
+
+        // Cut the document out of its surroundings and undo the perspective of phone photos
+        if let Some(page) = Self::normalize_page(&gray, None) {
+            log::debug!(
+                "Normalized {:?} page at {:?}, {:.2} pixels per mm",
+                page.format, page.quad.corners, page.pixels_per_mm
+            );
+            dpi = Some((page.pixels_per_mm * 25.4).round() as u32);
+            transforms.push(ImageTransform::PerspectiveWarp {
+                quad: page.quad,
+                format: page.format,
+                pixels_per_mm: page.pixels_per_mm,
+            });
+            gray = page.image;
+        }
+
+        // Turn sideways and upside-down scans upright before anything reads text
+        let oriented = Self::orient_image(&gray);
+        if oriented.rotation != 0 {
+            log::debug!("Rotated image by {} degrees", oriented.rotation);
+            transforms.push(ImageTransform::Rotate { degrees: oriented.rotation });
+            gray = oriented.image;
+        }
 
         // Deskew image
         let deskewed = Self::deskew_image(&gray);
+        log::debug!("Detected skew of {:.2} degrees", deskewed.angle);
+        transforms.push(ImageTransform::Deskew { angle: deskewed.angle });
+
+        // Contrast and binarization are left to the OCR stages, which need different treatment
+        // (see PreprocessOptions)
+        ProcessedImage::from_gray(deskewed.image, dpi, transforms)
+    }
+
+    /// Detect the document in a photo and warp it to the dimensions of its format (inferred
+    /// from the aspect ratio when not given), reporting the physical scale of the result
+    pub fn normalize_page(img: &GrayImage, format: Option<&DocumentFormat>) -> Option<NormalizedPage> {
+        PageNormalizer::normalize(img, format)
+    }
+
+    /// Measure blur, glare over the MRZ, resolution, exposure and cropping before running OCR.
+    /// Call `ensure_acceptable` on the report to turn the issues into an `ImageQualityError`.
+    pub fn assess_quality(image: &ProcessedImage, thresholds: &QualityThresholds) -> Result<ImageQuality, PassportError> {
+        QualityAnalyzer::assess(image, thresholds)
+    }
+
+    /// Resolution recorded in a JPEG (JFIF density) or PNG (pHYs chunk) file
+    pub fn read_dpi(data: &[u8]) -> Option<u32> {
+        let be16 = |at: usize| data.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as u32);
+        let be32 = |at: usize| data.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+
+        if data.starts_with(&[0xFF, 0xD8]) {
+            // Walk the marker segments up to the start of the image data
+            let mut at = 2;
+            while data.get(at) == Some(&0xFF) {
+                let marker = *data.get(at + 1)?;
+                let length = be16(at + 2)? as usize;
+                if marker == 0xE0 && data.get(at + 4..at + 9) == Some(&b"JFIF\0"[..]) {
+                    let density = be16(at + 12)?;
+                    return match data.get(at + 11)? {
+                        1 => Some(density),
+                        2 => Some((density as f32 * 2.54).round() as u32),
+                        _ => None,
+                    }
+                    .filter(|dpi| *dpi > 0);
+                }
+                if marker == 0xDA {
+                    break;
+                }
+                at += 2 + length;
+            }
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            let mut at = 8;
+            while let (Some(length), Some(kind)) = (be32(at), data.get(at + 4..at + 8)) {
+                if kind == b"pHYs" {
+                    // Pixels per unit, with the metre as the only defined unit
+                    let pixels_per_metre = be32(at + 8)?;
+                    return (data.get(at + 16) == Some(&1) && pixels_per_metre > 0)
+                        .then(|| (pixels_per_metre as f32 * 0.0254).round() as u32);
+                }
+                if kind == b"IDAT" {
+                    break;
+                }
+                at += 12 + length as usize;
+            }
+        }
+        None
+    }
+
+    fn color_model(color: ColorType) -> ColorModel {
+        match color {
+            ColorType::L8 | ColorType::L16 | ColorType::La8 | ColorType::La16 => ColorModel::Gray,
+            ColorType::Rgba8 | ColorType::Rgba16 | ColorType::Rgba32F => ColorModel::Rgba,
+            _ => ColorModel::Rgb,
+        }
+    }
+
+    /// Turn the image upright (text left to right, MRZ at the bottom), reporting the clockwise
+    /// rotation that was applied
+    pub fn orient_image(img: &GrayImage) -> OrientedImage {
+        OrientationDetector::orient(img)
+    }
+
+    /// Rotate the image so its text lines are horizontal, reporting the skew that was detected
+    pub fn deskew_image(img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> DeskewedImage {
+        Deskewer::deskew(img)
+    }
+
+    /// Find the MRZ band of a document image and crop it for OCR.
+    ///
+    /// A blackhat (closing minus image) brings out dark text on the light background and its
+    /// horizontal gradient highlights the dense strokes of OCR-B characters. Closing the
+    /// thresholded gradient with a wide kernel joins characters into lines and with a square one
+    /// joins the lines into a block. The lowest block that is wide and flat enough is the MRZ.
+    pub fn locate_mrz(img: &GrayImage) -> Option<MrzRegion> {
+        if img.width() == 0 || img.height() == 0 {
+            return None;
+        }
+        let scale = img.width() as f32 / Self::MRZ_ANALYSIS_WIDTH as f32;
+        let height = ((img.height() as f32 / scale).round() as u32).max(1);
+        let small = imageops::resize(img, Self::MRZ_ANALYSIS_WIDTH, height, FilterType::Triangle);
+        let small = imageproc::filter::gaussian_blur_f32(&small, 1.0);
+
+        // Blackhat with a 13x5 kernel, then the magnitude of the horizontal gradient
+        let closed = Self::min_filter(&Self::max_filter(&small, 6, 2), 6, 2);
+        let blackhat = ImageBuffer::from_fn(small.width(), small.height(), |x, y| {
+            Luma([closed.get_pixel(x, y)[0].saturating_sub(small.get_pixel(x, y)[0])])
+        });
+        let gradient = horizontal_sobel(&blackhat);
+        let max_gradient = gradient.pixels().map(|p| p[0].unsigned_abs()).max().unwrap_or(0).max(1);
+        let gradient: GrayImage = ImageBuffer::from_fn(small.width(), small.height(), |x, y| {
+            Luma([(gradient.get_pixel(x, y)[0].unsigned_abs() as u32 * 255 / max_gradient as u32) as u8])
+        });
+
+        // Join characters into lines, threshold, join lines into blocks and drop small specks
+        let lines = Self::min_filter(&Self::max_filter(&gradient, 6, 2), 6, 2);
+        let threshold = otsu_level(&lines);
+        let binary: GrayImage = ImageBuffer::from_fn(lines.width(), lines.height(), |x, y| {
+            Luma([if lines.get_pixel(x, y)[0] > threshold { 255 } else { 0 }])
+        });
+        let blocks = Self::min_filter(&Self::min_filter(&Self::max_filter(&binary, 10, 10), 10, 10), 4, 4);
+
+        let labels = connected_components(&blocks, Connectivity::Eight, Luma([0u8]));
+        let mut boxes: HashMap<u32, (u32, u32, u32, u32)> = HashMap::new();
+        for (x, y, label) in labels.enumerate_pixels() {
+            if label[0] == 0 {
+                continue;
+            }
+            let bounds = boxes.entry(label[0]).or_insert((x, y, x, y));
+            *bounds = (bounds.0.min(x), bounds.1.min(y), bounds.2.max(x), bounds.3.max(y));
+        }
+
+        let (x0, y0, x1, y1) = boxes
+            .into_values()
+            .filter(|(x0, y0, x1, y1)| {
+                let width = (x1 - x0 + 1) as f32;
+                let height = (y1 - y0 + 1) as f32;
+                width >= Self::MRZ_MIN_WIDTH_RATIO * small.width() as f32 && width / height >= Self::MRZ_MIN_ASPECT_RATIO
+            })
+            .max_by_key(|(_, _, _, y1)| *y1)?;
+
+        // Pad the band so that no character touches its edges, and map it back to the input
+        let pad_x = (x1 - x0 + 1) as f32 * 0.03;
+        let pad_y = (y1 - y0 + 1) as f32 * 0.2;
+        let left = ((x0 as f32 - pad_x) * scale).max(0.0) as u32;
+        let top = ((y0 as f32 - pad_y) * scale).max(0.0) as u32;
+        let right = (((x1 + 1) as f32 + pad_x) * scale).min(img.width() as f32) as u32;
+        let bottom = (((y1 + 1) as f32 + pad_y) * scale).min(img.height() as f32) as u32;
+        let bounds = BoundingBox {
+            x: left,
+            y: top,
+            width: right.saturating_sub(left).max(1),
+            height: bottom.saturating_sub(top).max(1),
+        };
+
+        let mut image = imageops::crop_imm(img, bounds.x, bounds.y, bounds.width, bounds.height).to_image();
+        if image.width() < Self::MRZ_OCR_WIDTH {
+            let factor = Self::MRZ_OCR_WIDTH as f32 / image.width() as f32;
+            let height = ((image.height() as f32 * factor).round() as u32).max(1);
+            image = imageops::resize(&image, Self::MRZ_OCR_WIDTH, height, FilterType::CatmullRom);
+        }
+        Some(MrzRegion { bounds, image })
+    }
+
+    /// Grayscale dilation with a (2 * radius_x + 1) x (2 * radius_y + 1) rectangle
+    pub(crate) fn max_filter(img: &GrayImage, radius_x: u32, radius_y: u32) -> GrayImage {
+        Self::rank_filter(img, radius_x, radius_y, u8::max)
+    }
+
+    /// Grayscale erosion with a (2 * radius_x + 1) x (2 * radius_y + 1) rectangle
+    pub(crate) fn min_filter(img: &GrayImage, radius_x: u32, radius_y: u32) -> GrayImage {
+        Self::rank_filter(img, radius_x, radius_y, u8::min)
+    }
 
-        // Enhance contrast
-        let enhanced = Self::enhance_contrast(&deskewed);
-
-        // Convert back to bytes
-        enhanced.into_raw()
-    }
-
-    fn deskew_image(img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> ImageBuffer<Luma<u8>, Vec<u8>> {
-        // Simple deskewing - rotate by a small angle
-        rotate_about_center(img, 0.0, Interpolation::Bilinear, Luma([0u8]))
-    }
-
-    fn enhance_contrast(img: &ImageBuffer<Luma<u8>, Vec<u8>>) -> ImageBuffer<Luma<u8>, Vec<u8>> {
-        // Simple contrast enhancement
-        let mut enhanced = img.clone();
-        for pixel in enhanced.pixels_mut() {
-            let value = pixel[0];
-            let new_value = if value < 128 {
-                value.saturating_sub(20)
-            } else {
-                value.saturating_add(20)
-            };
-            pixel[0] = new_value;
-        }
-        enhanced
-    }
-    
-    pub fn save_to_temp_file(image_data: &[u8]) -> Result<PathBuf, PassportError> {
-        let mut temp_file = tempfile::Builder::new()
-            .suffix(".jpg")
-            .tempfile()
-            .map_err(|e| PassportError::ImageProcessingError(e.to_string()))?;
-            
-        temp_file.write_all(image_data)
-            .map_err(|e| PassportError::ImageProcessingError(e.to_string()))?;
-            
-        Ok(temp_file.path().to_path_buf())
+    /// Apply a rectangular min or max filter as a horizontal pass followed by a vertical one
+    fn rank_filter(img: &GrayImage, radius_x: u32, radius_y: u32, pick: fn(u8, u8) -> u8) -> GrayImage {
+        let (width, height) = img.dimensions();
+        let horizontal = ImageBuffer::from_fn(width, height, |x, y| {
+            let from = x.saturating_sub(radius_x);
+            let to = (x + radius_x).min(width - 1);
+            (from..=to).map(|i| img.get_pixel(i, y)[0]).reduce(pick).map_or(Luma([0]), |v| Luma([v]))
+        });
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let from = y.saturating_sub(radius_y);
+            let to = (y + radius_y).min(height - 1);
+            (from..=to).map(|j| horizontal.get_pixel(x, j)[0]).reduce(pick).map_or(Luma([0]), |v| Luma([v]))
+        })
     }
 }
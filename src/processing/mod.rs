@@ -1,5 +1,17 @@
+pub mod deskew;
+pub mod enhance;
+pub mod hocr;
 pub mod image;
 pub mod ocr;
+pub mod orientation;
+pub mod perspective;
+pub mod quality;
 
-pub use image::ImageProcessor;
+pub use deskew::{DeskewedImage, Deskewer};
+pub use enhance::{Binarization, Clahe, EnhanceOptions, ImageEnhancer, PreprocessOptions};
+pub use hocr::{HocrParser, OcrLine};
+pub use image::{BoundingBox, ColorModel, ImageProcessor, ImageTransform, MrzRegion, ProcessedImage};
 pub use ocr::OcrProcessor;
+pub use orientation::{OrientationDetector, OrientedImage};
+pub use perspective::{DocumentQuad, NormalizedPage, PageNormalizer};
+pub use quality::{Cropping, ImageQuality, QualityAnalyzer, QualityIssue, QualityThresholds};
@@ -1,10 +1,15 @@
-use std::io::Write;
 use std::path::Path;
+use std::collections::HashMap;
 use std::process::Command;
-use tempfile::NamedTempFile;
-use tesseract::Tesseract;
+use tesseract::{PageSegMode, Tesseract};
 use crate::utils::PassportError;
-use crate::models::{MrzData, CheckDigits, VisualData, DocumentFormat};
+use crate::models::{DocumentDate, FieldConfidence, MrzData, Sex, VisualData};
+use crate::mrz::{
+    DateKind, DatePolicy, LocatedMrz, MrzAligner, MrzCorrector, MrzHypotheses, MrzHypothesis, MrzLocator, ParseOptions,
+};
+use crate::processing::{
+    EnhanceOptions, HocrParser, ImageEnhancer, ImageProcessor, OcrLine, PreprocessOptions, ProcessedImage,
+};
 use whatlang::{detect, Lang};
 use regex::Regex;
 
@@ -50,34 +55,8 @@ impl OcrProcessor {
         None
     }
 
-    /// Clean alphanumeric MRZ field by correcting OCR confusions and removing fillers.
-    fn clean_mrz_alphanumeric_field(field: &str) -> String {
-        field.chars()
-            .map(|c| {
-                let c = c.to_ascii_uppercase();
-                match c {
-                    'I' | 'L' | '|' => '1',
-                    'Z' => '2',
-                    'S' => '5',
-                    'G' => '6',
-                    'B' => '8',
-                    other => other,
-                }
-            })
-            .filter(|c| c.is_ascii_alphanumeric())
-            .collect()
-    }
-
     // Get full OCR text from image
-    pub fn get_full_ocr_text(image_data: &[u8]) -> Result<String, PassportError> {
-        // Create a temporary file to write the image data to
-        let mut temp_file = NamedTempFile::new().map_err(|e| PassportError::IoError(e.to_string()))?;
-        temp_file.write_all(image_data).map_err(|e| PassportError::IoError(e.to_string()))?;
-        
-        // Get the path as a string
-        let image_path_str = temp_file.path().to_str()
-            .ok_or_else(|| PassportError::IoError("Failed to convert path to string".to_string()))?;
-        
+    pub fn get_full_ocr_text(image: &ProcessedImage) -> Result<String, PassportError> {
         let datapath_prefix = Self::tessdata_prefix();
         if let Some(ref path) = datapath_prefix {
             std::env::set_var("TESSDATA_PREFIX", path);
@@ -103,27 +82,166 @@ impl OcrProcessor {
                 }
             }
         };
-        let text = tess.set_image(image_path_str)
-            .map_err(|e| PassportError::MrzExtractionError(format!("Tesseract set image error: {}", e)))?
+        let text = Self::set_frame(tess, image, PassportError::MrzExtractionError)?
             .get_text()
             .map_err(|e| PassportError::MrzExtractionError(format!("Tesseract get text error: {}", e)))?;
         
         Ok(text)
     }
     // Extract MRZ data from the processed image
-    pub fn extract_mrz(image_data: &[u8]) -> Result<MrzData, PassportError> {
+    pub fn extract_mrz(image: &ProcessedImage) -> Result<MrzData, PassportError> {
+        Self::extract_mrz_with_options(image, &ParseOptions::default())
+    }
+
+    /// Extract MRZ data, choosing between strict and lenient parsing of the recognized lines
+    pub fn extract_mrz_with_options(image: &ProcessedImage, options: &ParseOptions) -> Result<MrzData, PassportError> {
+        Self::extract_mrz_with_preprocessing(image, options, &PreprocessOptions::default())
+    }
+
+    /// Extract MRZ data, enhancing the image with the MRZ stage of the preprocessing options
+    pub fn extract_mrz_with_preprocessing(
+        image: &ProcessedImage,
+        options: &ParseOptions,
+        preprocessing: &PreprocessOptions,
+    ) -> Result<MrzData, PassportError> {
         println!("Extracting MRZ data from image...");
+        let ocr_lines = Self::recognize_mrz(image, &preprocessing.mrz)?;
+        let text = HocrParser::text(&ocr_lines);
+
+        println!("MRZ OCR result:\n{}", text);
         
-        // Create a temporary file from the image data
-        let mut temp_file = NamedTempFile::new()
-            .map_err(|e| PassportError::MrzExtractionError(format!("Failed to create temp file: {}", e)))?;
-            
-        temp_file.write_all(image_data)
-            .map_err(|e| PassportError::MrzExtractionError(format!("Failed to write to temp file: {}", e)))?;
-            
-        let image_path_str = temp_file.path().to_str()
-            .ok_or_else(|| PassportError::MrzExtractionError("Failed to convert path to string".to_string()))?;
-        
+        // Find the block of consecutive lines that best fits an MRZ layout, keeping line order
+        let located = MrzLocator::locate(&text);
+        match &located {
+            Some(located) => log::debug!(
+                "Located {:?} MRZ at line {} (score {:.2}): {:?}",
+                located.format,
+                located.source_lines[0] + 1,
+                located.score,
+                located.lines
+            ),
+            None => log::debug!("No MRZ block found in OCR text"),
+        }
+
+        if let Some(located) = located {
+            let line_refs: Vec<&str> = located.lines.iter().map(|l| l.as_str()).collect();
+
+            // Use the check digits to undo OCR letter/digit confusions before slicing fields
+            let corrected = MrzCorrector::correct(&line_refs, &located.format);
+            for field in &corrected.unresolved {
+                log::warn!("{} check digit could not be satisfied", field.name());
+            }
+
+            let confidences = Self::located_confidences(&located, &ocr_lines);
+            let mrz_data = MrzHypotheses::read(&located, &corrected, &confidences, options)
+                .map_err(|e| PassportError::MrzExtractionError(e.to_string()))?;
+            for correction in &mrz_data.corrections {
+                log::debug!(
+                    "Corrected {} at line {} position {}: '{}' -> '{}'",
+                    correction.field,
+                    correction.line + 1,
+                    correction.position + 1,
+                    correction.original,
+                    correction.corrected
+                );
+            }
+            for confidence in mrz_data.confidences.iter().filter(|c| !c.uncertain.is_empty()) {
+                log::debug!("Low confidence: {}", confidence);
+            }
+            for field in &mrz_data.unreadable {
+                log::warn!("{} unreadable: {}", field.field, field.reason);
+            }
+            for repair in &mrz_data.repairs {
+                log::debug!("Repaired {}: '{}' -> '{}' ({})", repair.field, repair.original, repair.repaired, repair.reason);
+            }
+
+            println!("Successfully extracted MRZ data");
+            Ok(mrz_data)
+        } else {
+            Err(PassportError::MrzExtractionError(
+                "Failed to extract valid MRZ lines".to_string()
+            ))
+        }
+    }
+
+    /// Extract up to `count` alternative MRZ readings, best first.
+    ///
+    /// Readings come from the best-scoring MRZ blocks in the OCR text and from every way of
+    /// resolving check digits the correction could not settle on its own. Each is ranked by OCR
+    /// confidence, check-digit agreement and field plausibility, so an operator can pick among
+    /// them instead of re-scanning the document.
+    pub fn extract_mrz_candidates(
+        image: &ProcessedImage,
+        options: &ParseOptions,
+        count: usize,
+    ) -> Result<Vec<MrzHypothesis>, PassportError> {
+        Self::extract_mrz_candidates_with_preprocessing(image, options, count, &PreprocessOptions::default())
+    }
+
+    /// Extract up to `count` alternative MRZ readings, enhancing the image with the MRZ stage
+    /// of the preprocessing options
+    pub fn extract_mrz_candidates_with_preprocessing(
+        image: &ProcessedImage,
+        options: &ParseOptions,
+        count: usize,
+        preprocessing: &PreprocessOptions,
+    ) -> Result<Vec<MrzHypothesis>, PassportError> {
+        let ocr_lines = Self::recognize_mrz(image, &preprocessing.mrz)?;
+        let text = HocrParser::text(&ocr_lines);
+
+        let hypotheses: Vec<MrzHypothesis> = MrzLocator::candidates(&text)
+            .iter()
+            .take(count)
+            .flat_map(|located| {
+                let confidences = Self::located_confidences(located, &ocr_lines);
+                MrzHypotheses::from_located(located, &confidences, options)
+            })
+            .collect();
+        let ranked = MrzHypotheses::rank(hypotheses, count);
+        if ranked.is_empty() {
+            return Err(PassportError::MrzExtractionError(
+                "Failed to extract valid MRZ lines".to_string()
+            ));
+        }
+        for (index, hypothesis) in ranked.iter().enumerate() {
+            log::debug!(
+                "MRZ reading {} (score {:.2}, OCR {:.2}, check digits {:.2}, plausibility {:.2}): {:?}",
+                index + 1,
+                hypothesis.score,
+                hypothesis.ocr_confidence,
+                hypothesis.check_digit_agreement,
+                hypothesis.plausibility,
+                hypothesis.data.raw_mrz_lines
+            );
+        }
+        Ok(ranked)
+    }
+
+    /// Recognize the MRZ lines of an image with the OCR-B model, or the best fallback available.
+    ///
+    /// When the MRZ band can be located, only the band is read, as a single block of text, so
+    /// the photo and VIZ text cannot pollute the read. The band (or the whole image) is
+    /// enhanced with the given options first.
+    fn recognize_mrz(image: &ProcessedImage, enhance: &EnhanceOptions) -> Result<Vec<OcrLine>, PassportError> {
+        let gray = image.to_gray().map_err(|e| PassportError::MrzExtractionError(e.to_string()))?;
+        let region = ImageProcessor::locate_mrz(&gray);
+        let (target, dpi) = match &region {
+            Some(region) => {
+                log::debug!(
+                    "Located MRZ band at ({}, {}), {}x{} pixels",
+                    region.bounds.x, region.bounds.y, region.bounds.width, region.bounds.height
+                );
+                // The band was upscaled, and its resolution with it
+                let scale = region.image.width() as f32 / region.bounds.width as f32;
+                (&region.image, image.dpi.map(|dpi| (dpi as f32 * scale).round() as u32))
+            }
+            None => {
+                log::debug!("MRZ band not found, reading the whole image");
+                (&gray, image.dpi)
+            }
+        };
+        let enhanced = ProcessedImage::from_gray(ImageEnhancer::enhance(target, enhance), dpi, image.transforms.clone());
+
         let datapath_prefix = Self::tessdata_prefix();
         if let Some(ref path) = datapath_prefix {
             std::env::set_var("TESSDATA_PREFIX", path);
@@ -149,250 +267,87 @@ impl OcrProcessor {
                 }
             }
         };
-        let text = tess.set_image(image_path_str)
-            .map_err(|e| PassportError::MrzExtractionError(format!("Tesseract set image error: {}", e)))?
+        let mut tess = Self::set_frame(tess, &enhanced, PassportError::MrzExtractionError)?
             .set_variable("tessedit_char_whitelist", "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789<")
-            .map_err(|e| PassportError::MrzExtractionError(format!("Tesseract set variable error: {}", e)))?
-            .get_text()
-            .map_err(|e| PassportError::MrzExtractionError(format!("Tesseract error: {}", e)))?;
-            
-        println!("MRZ OCR result:\n{}", text);
-        
-        // Collect and clean each OCR line
-        let lines: Vec<&str> = text.lines().collect();
-        let cleaned_candidates: Vec<String> = lines.iter()
-            .map(|line| Self::clean_mrz_line(line))
-            .collect();
-
-        // Filter MRZ candidate lines by length (40 to 44 chars) and valid MRZ charset
-        let mut candidates: Vec<String> = cleaned_candidates.iter().cloned()
-            .filter(|l| (40..=44).contains(&l.len())
-                         && l.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '<'))
-            .collect();
-        // Fallback: if filter yielded less than 2, try lines starting with 'P<' and next line
-        if candidates.len() < 2 {
-            if let Some(pos) = cleaned_candidates.iter().position(|l| l.starts_with("P<")) {
-                if pos + 1 < cleaned_candidates.len() {
-                    candidates = vec![
-                        cleaned_candidates[pos].clone(),
-                        cleaned_candidates[pos + 1].clone(),
-                    ];
-                }
-            }
-        }
-        // Sort by descending length to prioritize full MRZ lines
-        candidates.sort_by(|a, b| b.len().cmp(&a.len()));
-        // Select the top two MRZ lines and pad/truncate to the expected length
-        let raw_lines: Vec<String> = candidates.into_iter().take(2).collect();
-        let expected_chars = DocumentFormat::TD3.mrz_chars_per_line();
-        let mrz_lines: Vec<String> = raw_lines
-            .iter()
-            .map(|l| {
-                let mut s = l.clone();
-                if s.len() > expected_chars {
-                    s.truncate(expected_chars);
-                } else if s.len() < expected_chars {
-                    s.extend(std::iter::repeat('<').take(expected_chars - s.len()));
-                }
-                s
-            })
-            .collect();
-        println!("Filtered and padded MRZ lines: {:?}", mrz_lines);
-        
-        // If we found at least 2 valid MRZ lines, parse them
-        if mrz_lines.len() >= 2 {
-            // In TD3 format (passport), MRZ consists of two lines of 44 characters each
-            let line1 = if mrz_lines[0].len() > 43 { &mrz_lines[0][0..44] } else { &mrz_lines[0] };
-            let line2 = if mrz_lines[1].len() > 43 { &mrz_lines[1][0..44] } else { &mrz_lines[1] };
-            
-            // Clean and validate birth date
-            let raw_birth_date = &line2[13..19];
-            // Use specialized MRZ date parser for more tolerance
-            let mrz_date_of_birth = match Self::parse_mrz_date(raw_birth_date) {
-                Some(date) => {
-                    // The date is in YYMMDD format, but we need to check if it's actually correct
-                    // For birth dates in MRZ, we need to interpret the date correctly
-                    let year_str = &date[0..2];
-                    let month_str = &date[2..4];
-                    let day_str = &date[4..6];
-                    
-                    let year = year_str.parse::<u32>().unwrap_or(0);
-                    let month = month_str.parse::<u32>().unwrap_or(0);
-                    let day = day_str.parse::<u32>().unwrap_or(0);
-                    
-                    // Determine full year (assuming 19xx for birth years, 20xx for recent years)
-                    let full_year = if year < 30 { 2000 + year } else { 1900 + year };
-                    
-                    println!("Successfully parsed birth date: {:02}/{:02}/{:04}", day, month, full_year);
-                    
-                    // Format properly for display in verification report
-                    let formatted_date = format!("{:02} {:02} {:04}", day, month, full_year);
-                    formatted_date
-                },
-                None => return Err(PassportError::MrzExtractionError("Invalid birth date after parsing".to_string())),
-            };
-            
-            // Store the formatted date
-            let date_of_birth = mrz_date_of_birth;
-            
-            // Clean and validate expiry date
-            let raw_expiry_date = &line2[21..27];
-            // Use specialized MRZ date parser for more tolerance
-            let mrz_date_of_expiry = match Self::parse_mrz_date(raw_expiry_date) {
-                Some(date) => {
-                    // For expiry dates in MRZ, we need to interpret the date correctly
-                    let year_str = &date[0..2];
-                    let month_str = &date[2..4];
-                    let day_str = &date[4..6];
-                    
-                    let year = year_str.parse::<u32>().unwrap_or(0);
-                    let month = month_str.parse::<u32>().unwrap_or(0);
-                    let day = day_str.parse::<u32>().unwrap_or(0);
-                    
-                    // For expiry dates, we always assume 20xx
-                    let full_year = 2000 + year;
-                    
-                    println!("Successfully parsed expiry date: {:02}/{:02}/{:04}", day, month, full_year);
-                    
-                    // Format properly for display in verification report
-                    let formatted_date = format!("{:02} {:02} {:04}", day, month, full_year);
-                    formatted_date
-                },
-                None => return Err(PassportError::MrzExtractionError("Invalid expiry date after parsing".to_string())),
-            };
-            
-            // Store the formatted date
-            let date_of_expiry = mrz_date_of_expiry;
-            
-            // Parse MRZ data following the ICAO 9303 standard for TD3 documents
-            // Line 1: Positions 1-2 (Document type), 3-5 (Issuing country), 6-44 (Name)
-            let document_type = if line1.len() > 1 { line1[0..1].to_string() } else { "P".to_string() };
-            let raw_issuing_country = if line1.len() > 5 { &line1[2..5] } else { "MEX" };
-            let issuing_country = Self::clean_mrz_alpha_field(raw_issuing_country);
-            
-            // Extract and clean name parts
-            let name_part = if line1.len() > 6 { &line1[5..] } else { "" };
-            let name_parts: Vec<&str> = name_part.split("<<").collect();
-            let raw_surname = name_parts.get(0).unwrap_or(&"");
-            let surname = if !raw_surname.is_empty() {
-                let cleaned = Self::clean_mrz_alpha_field(raw_surname);
-                cleaned.replace("<", " ").trim().to_string()
-            } else {
-                "UNKNOWN".to_string()
-            };
-            let raw_given = name_parts.get(1).unwrap_or(&"");
-            let given_names = if !raw_given.is_empty() {
-                let cleaned = Self::clean_mrz_alpha_field(raw_given);
-                cleaned.replace("<", " ").trim().to_string()
-            } else {
-                "UNKNOWN".to_string()
-            };
-            
-            // Line 2: Positions 1-9 (Document number), 10 (Check digit), 11-13 (Nationality), 
-            // 14-19 (Birth date), 20 (Check digit), 21 (Sex), 22-27 (Expiry date), 
-            // 28 (Check digit), 29-42 (Personal number), 43 (Check digit), 44 (Composite check digit)
-            let raw_doc = if line2.len() > 9 { &line2[0..9] } else { "" };
-            let document_number = if !raw_doc.is_empty() {
-                let s = Self::clean_mrz_alphanumeric_field(raw_doc);
-                if s.is_empty() { "UNKNOWN".to_string() } else { s }
-            } else {
-                "UNKNOWN".to_string()
-            };
-            let raw_nationality = if line2.len() > 12 { &line2[9..12] } else { "MEX" };
-            let nationality = Self::clean_mrz_alpha_field(raw_nationality);
-            let gender = if line2.len() > 21 { line2[20..21].to_string() } else { "X".to_string() };
-            // Extract and clean expiry date (positions 22-27 in MRZ)
-            let date_of_expiry = date_of_expiry;
-            
-            // Extract check digits
-            let doc_check = if line2.len() > 10 { line2.chars().nth(9).unwrap_or('0') } else { '0' };
-            let dob_check = if line2.len() > 20 { line2.chars().nth(19).unwrap_or('0') } else { '0' };
-            let exp_check = if line2.len() > 28 { line2.chars().nth(27).unwrap_or('0') } else { '0' };
-            let pers_check = if line2.len() > 43 { line2.chars().nth(42).unwrap_or('0') } else { '0' };
-            let comp_check = if line2.len() > 44 { line2.chars().nth(43).unwrap_or('0') } else { '0' };
-            
-            let mrz_data = MrzData {
-                document_type,
-                issuing_country,
-                surname,
-                given_names,
-                document_number,
-                nationality,
-                date_of_birth,
-                gender,
-                date_of_expiry,
-                personal_number: None,
-                check_digits: CheckDigits {
-                    document_number_check: doc_check,
-                    date_of_birth_check: dob_check,
-                    date_of_expiry_check: exp_check,
-                    personal_number_check: pers_check,
-                    composite_check: comp_check,
-                },
-                document_format: Some(DocumentFormat::TD3),
-                optional_data: None,
-                raw_mrz_lines: mrz_lines,
-            };
-            
-            println!("Successfully extracted MRZ data");
-            Ok(mrz_data)
-        } else {
-            return Err(PassportError::MrzExtractionError(
-                "Failed to extract valid MRZ lines".to_string()
-            ));
+            .map_err(|e| PassportError::MrzExtractionError(format!("Tesseract set variable error: {}", e)))?;
+        if region.is_some() {
+            tess.set_page_seg_mode(PageSegMode::PsmSingleBlock);
         }
+        Self::recognize_lines(tess, PassportError::MrzExtractionError)
     }
 
-    /// Clean up an MRZ line by stripping whitespace and uppercasing
-    fn clean_mrz_line(line: &str) -> String {
-        let line = line.trim();
-        let mut cleaned = String::with_capacity(line.len());
-        for c in line.chars() {
-            if c.is_whitespace() {
-                continue;
-            }
-            cleaned.push(c.to_ascii_uppercase());
-        }
- 
-        // Return cleaned MRZ line without altering character count
-        cleaned
+    /// Hand the pixels of an image to Tesseract, with its resolution when known
+    fn set_frame(tess: Tesseract, image: &ProcessedImage, error: fn(String) -> PassportError) -> Result<Tesseract, PassportError> {
+        let tess = tess
+            .set_frame(
+                &image.pixels,
+                image.width as i32,
+                image.height as i32,
+                image.color_model.bytes_per_pixel() as i32,
+                image.bytes_per_line() as i32,
+            )
+            .map_err(|e| error(format!("Tesseract set image error: {}", e)))?;
+        Ok(match image.dpi {
+            Some(dpi) => tess.set_source_resolution(dpi as i32),
+            None => tess,
+        })
     }
 
-    /// Clean alphabetic MRZ field by correcting OCR confusions between digits and letters.
-    fn clean_mrz_alpha_field(field: &str) -> String {
-        field.chars().map(|c| {
-            let c = c.to_ascii_uppercase();
-            match c {
-                '0' => 'O',
-                '1' => 'I',
-                '2' => 'Z',
-                '3' => 'E',
-                '4' => 'A',
-                '5' => 'S',
-                '6' => 'G',
-                '7' => 'T',
-                '8' => 'B',
-                other => other,
-            }
-        }).collect()
+    /// Recognize the image and return its lines with per-character confidences, reporting
+    /// Tesseract failures with the given error kind
+    fn recognize_lines(tess: Tesseract, error: fn(String) -> PassportError) -> Result<Vec<OcrLine>, PassportError> {
+        let mut tess = tess
+            .set_variable("hocr_char_boxes", "1")
+            .map_err(|e| error(format!("Tesseract set variable error: {}", e)))?;
+        let hocr = tess
+            .get_hocr_text(0)
+            .map_err(|e| error(format!("Tesseract error: {}", e)))?;
+        Ok(HocrParser::parse(&hocr))
+    }
+
+    /// Character confidences of the lines of a located MRZ block, carried through re-alignment;
+    /// inserted fillers were never read
+    fn located_confidences(located: &LocatedMrz, ocr_lines: &[OcrLine]) -> Vec<Vec<f32>> {
+        let line_length = located.format.mrz_chars_per_line();
+        located
+            .source_lines
+            .iter()
+            .enumerate()
+            .map(|(index, source)| {
+                let confidences: Vec<f32> = ocr_lines
+                    .get(*source)
+                    .map(|line| line.characters().iter().map(|(_, c)| *c).collect())
+                    .unwrap_or_default();
+                MrzAligner::apply_edits(&confidences, index, &located.edits, 0.0, line_length)
+            })
+            .collect()
     }
 
     // Extract visual data using OCR
-    pub fn extract_visual_data(image_data: &[u8]) -> Result<VisualData, PassportError> {
+    pub fn extract_visual_data(image: &ProcessedImage) -> Result<VisualData, PassportError> {
+        Self::extract_visual_data_with_preprocessing(image, &PreprocessOptions::default())
+    }
+
+    /// Extract visual data, enhancing the image with the stage of the preprocessing options
+    /// that is being read (MRZ or VIZ)
+    pub fn extract_visual_data_with_preprocessing(
+        image: &ProcessedImage,
+        preprocessing: &PreprocessOptions,
+    ) -> Result<VisualData, PassportError> {
         println!("Extracting visual data from image...");
         
         // Extract MRZ data first to prepopulate core fields
-        let mrz_data = Self::extract_mrz(image_data)?;
+        let mrz_data = Self::extract_mrz_with_preprocessing(image, &ParseOptions::default(), preprocessing)?;
         let mut document_number = mrz_data.document_number.clone();
         let mut surname = mrz_data.surname.clone();
         let mut given_names = mrz_data.given_names.clone();
         let mut date_of_birth = mrz_data.date_of_birth.clone();
         let nationality = mrz_data.nationality.clone();
         let issuing_country = mrz_data.issuing_country.clone();
-        let gender = mrz_data.gender.clone();
+        let mut sex = mrz_data.sex;
         let mut place_of_birth: Option<String> = None;
-        let mut date_of_issue = String::new();
-        let mut date_of_expiry = String::new();
+        let mut issue_text = String::new();
+        let mut expiry_text = String::new();
         let mut authority: Option<String> = None;
         let personal_number = mrz_data.personal_number.clone();
         // Prepare regex patterns for extracting optional fields
@@ -400,18 +355,20 @@ impl OcrProcessor {
         let re_issue = Regex::new(r"(?i)date of issu(?:e)?[:\s]*(\d{2}[ ./\-]\d{2}[ ./\-]\d{4})").unwrap();
         let re_expiry = Regex::new(r"(?i)date of expi(?:ry)?[:\s]*(\d{2}[ ./\-]\d{2}[ ./\-]\d{4})").unwrap();
         let re_authority = Regex::new(r"(?i)authority[:\s]*(.+)").unwrap();
+        let re_sex = Regex::new(r"(?i)\b(?:sex|sexe|sexo|geschlecht|пол|性别)(?:\s*/\s*(?:sex|sexe|sexo|geschlecht|пол))*[:\s]+(\S+)").unwrap();
+        let mut visual_sex: Option<Sex> = None;
         let mut visual_document_number: Option<String> = None;
         let mut visual_name: Option<String> = None;
         let re_doc_number = Regex::new(r"(?i)document number[:\s]*([A-Z0-9<]+)").unwrap();
         let re_name = Regex::new(r"(?i)(surname|given names)[:\s]*(.+)").unwrap();
         let mut visual_date_of_birth: Option<String> = None;
-        let re_birth = Regex::new(r"(?i)(date of birth|fecha de nacimiento)[:\s]*(\d{1,2}[/.-]\d{1,2}[/.-]\d{2,4}|\d{6,8})").unwrap(); // Capture date pattern directly
+        let re_birth = Regex::new(r"(?i)(date of birth|fecha de nacimiento)[:\s]*((?:\d{1,2}|XX)[/.-](?:\d{1,2}|XX)[/.-]\d{2,4}|\d{6,8})").unwrap(); // Capture date pattern directly
         let mut visual_date_of_issue: Option<String> = None;
-        let re_issue_date = Regex::new(r"(?i)(date of issue|fecha de expedicion)[:\s]*(\d{1,2}[/.-]\d{1,2}[/.-]\d{2,4}|\d{6,8})").unwrap(); // Capture date pattern
+        let re_issue_date = Regex::new(r"(?i)(date of issue|fecha de expedicion)[:\s]*((?:\d{1,2}|XX)[/.-](?:\d{1,2}|XX)[/.-]\d{2,4}|\d{6,8})").unwrap(); // Capture date pattern
         let mut visual_date_of_expiry: Option<String> = None;
-        let re_expiry_date = Regex::new(r"(?i)(date of expiry|fecha de caducidad|date of expiration)[:\s]*(\d{1,2}[/.-]\d{1,2}[/.-]\d{2,4}|\d{6,8})").unwrap(); // Capture date pattern
+        let re_expiry_date = Regex::new(r"(?i)(date of expiry|fecha de caducidad|date of expiration)[:\s]*((?:\d{1,2}|XX)[/.-](?:\d{1,2}|XX)[/.-]\d{2,4}|\d{6,8})").unwrap(); // Capture date pattern
         // Automatic language detection using full OCR text
-        let initial_text = Self::get_full_ocr_text(image_data)?;
+        let initial_text = Self::get_full_ocr_text(image)?;
         let info = detect(&initial_text)
             .ok_or_else(|| PassportError::FormatError("Language detection failed".to_string()))?;
         let detected_lang_code = match info.lang() {
@@ -427,16 +384,13 @@ impl OcrProcessor {
         let tess_langs = format!("ocrb+{}", detected_lang_code);
         println!("Using OCR model: {}", tess_langs);
         
-        // Create a temporary file from the image data
-        let mut temp_file = NamedTempFile::new()
-            .map_err(|e| PassportError::FormatError(format!("Failed to create temp file: {}", e)))?;
-            
-        temp_file.write_all(image_data)
-            .map_err(|e| PassportError::FormatError(format!("Failed to write to temp file: {}", e)))?;
-            
-        let image_path_str = temp_file.path().to_str()
-            .ok_or_else(|| PassportError::FormatError("Failed to convert path to string".to_string()))?;
-        
+        let gray = image.to_gray().map_err(|e| PassportError::FormatError(e.to_string()))?;
+        let enhanced = ProcessedImage::from_gray(
+            ImageEnhancer::enhance(&gray, &preprocessing.viz),
+            image.dpi,
+            image.transforms.clone(),
+        );
+
         let datapath_prefix = Self::tessdata_prefix();
         if let Some(ref path) = datapath_prefix {
             std::env::set_var("TESSDATA_PREFIX", path);
@@ -460,74 +414,106 @@ impl OcrProcessor {
                 }
             }
         };
-        let text = tess.set_image(image_path_str)
-            .map_err(|e| PassportError::FormatError(format!("Tesseract set image error: {}", e)))?
-            .get_text()
-            .map_err(|e| PassportError::FormatError(format!("Tesseract error: {}", e)))?;
-            
-        println!("Visual OCR result:\n{}", text);
-        
+        let tess = Self::set_frame(tess, &enhanced, PassportError::FormatError)?;
+        let ocr_lines = Self::recognize_lines(tess, PassportError::FormatError)?;
+
+        println!("Visual OCR result:\n{}", HocrParser::text(&ocr_lines));
+
+        // Confidence of every captured value, by capture, until it is known which captures are used
+        let mut captured: HashMap<&str, FieldConfidence> = HashMap::new();
+
         // Extract optional visual fields from OCR text using regex
-        for line in text.lines() {
-            let line = line.trim();
+        for ocr_line in &ocr_lines {
+            let line = ocr_line.text.as_str();
+            let mut capture = |key, field, m: Option<regex::Match>| {
+                if let Some(m) = m {
+                    captured.insert(key, ocr_line.confidence(field, m.range()));
+                }
+            };
             if place_of_birth.is_none() {
                 if let Some(cap) = re_place.captures(line) {
                     place_of_birth = Some(cap[1].trim().to_string());
+                    capture("place", "Place of birth", cap.get(1));
                 }
             }
-            if date_of_issue.is_empty() {
+            if issue_text.is_empty() {
                 if let Some(cap) = re_issue.captures(line) {
-                    date_of_issue = cap[1].trim().to_string();
+                    issue_text = cap[1].trim().to_string();
+                    capture("issue", "Date of issue", cap.get(1));
                 }
             }
-            if date_of_expiry.is_empty() {
+            if expiry_text.is_empty() {
                 if let Some(cap) = re_expiry.captures(line) {
-                    date_of_expiry = cap[1].trim().to_string();
+                    expiry_text = cap[1].trim().to_string();
+                    capture("expiry", "Date of expiry", cap.get(1));
                 }
             }
             if authority.is_none() {
                 if let Some(cap) = re_authority.captures(line) {
                     authority = Some(cap[1].trim().to_string());
+                    capture("authority", "Authority", cap.get(1));
+                }
+            }
+            if visual_sex.is_none() {
+                if let Some(cap) = re_sex.captures(line) {
+                    visual_sex = Sex::from_viz_label(&cap[1]);
+                    capture("sex", "Sex", cap.get(1));
                 }
             }
             if visual_document_number.is_none() {
                 if let Some(cap) = re_doc_number.captures(line) {
                     visual_document_number = Some(cap[1].trim().to_string());
+                    capture("document_number", "Document number", cap.get(1));
                 }
             }
             if visual_name.is_none() {
                 if let Some(cap) = re_name.captures(line) {
-                    visual_name = Some(cap[1].trim().to_string());
+                    visual_name = Some(cap[2].trim().to_string());
+                    capture("name", "Name", cap.get(2));
                 }
             }
             if visual_date_of_birth.is_none() {
                 if let Some(cap) = re_birth.captures(line) {
                     let date_str = cap.get(2).map_or("", |m| m.as_str()); // Get the captured date string group
                     visual_date_of_birth = Some(date_str.to_string());
+                    capture("birth_date", "Date of birth", cap.get(2));
                 }
             }
             if visual_date_of_issue.is_none() {
                 if let Some(cap) = re_issue_date.captures(line) {
                     let date_str = cap.get(2).map_or("", |m| m.as_str()); // Get the captured date string group
                     visual_date_of_issue = Some(date_str.to_string());
+                    capture("issue_date", "Date of issue", cap.get(2));
                 }
             }
             if visual_date_of_expiry.is_none() {
                 if let Some(cap) = re_expiry_date.captures(line) {
                     let date_str = cap.get(2).map_or("", |m| m.as_str()); // Get the captured date string group
                     visual_date_of_expiry = Some(date_str.to_string());
+                    capture("expiry_date", "Date of expiry", cap.get(2));
                 }
             }
         }
         
+        // Fields keep the MRZ confidence unless their value is taken from the VIZ
+        let mut confidences = mrz_data.confidences.clone();
+        let mut use_capture = |key: &str| {
+            if let Some(confidence) = captured.remove(key) {
+                confidences.retain(|c| c.field != confidence.field);
+                confidences.push(confidence);
+            }
+        };
+
         // Override MRZ data if visual extraction is valid
         if let Some(vis_doc) = &visual_document_number {
             if vis_doc.len() == document_number.len() && vis_doc.chars().all(|c| c.is_alphanumeric()) {
                 document_number = vis_doc.clone();
+                use_capture("document_number");
             }
         }
         if let Some(vis_name) = &visual_name {
             if !vis_name.is_empty() {
+                use_capture("name");
                 // Simple override for name; in a real scenario, consider parsing or confidence scoring
                 let name_parts: Vec<&str> = vis_name.split_whitespace().collect();
                 if name_parts.len() >= 2 {
@@ -538,35 +524,56 @@ impl OcrProcessor {
                 }
             }
         }
-        if date_of_birth == "INVALID" {
-            if let Some(vis_birth) = &visual_date_of_birth {
-                // Simple parsing: assume visual date is in DD/MM/YYYY or similar, convert to YYMMDD for consistency
-                if let Some(parsed_date) = Self::parse_visual_date(vis_birth) {
-                    date_of_birth = parsed_date; // Set to formatted YYMMDD string
-                }
-            }
+        // The printed sex is kept as read so it can be cross-checked against the MRZ
+        if visual_sex.is_some() {
+            sex = visual_sex;
+            use_capture("sex");
         }
-        if date_of_expiry == "INVALID" {
-            if let Some(vis_expiry) = &visual_date_of_expiry { // Assuming visual_date_of_expiry is already extracted
-                if let Some(parsed_date) = Self::parse_visual_date(vis_expiry) {
-                    date_of_expiry = parsed_date;
-                }
+        // VIZ dates are read with the same year resolution as the MRZ
+        let policy = DatePolicy::default();
+        if !date_of_birth.is_complete() {
+            let parsed = visual_date_of_birth.as_deref().and_then(|d| {
+                Self::parse_visual_date(d, |yy, month, day| policy.resolve_year(DateKind::Birth, yy, month, day))
+            });
+            if let Some(parsed) = parsed {
+                date_of_birth = parsed;
+                use_capture("birth_date");
             }
         }
-        if date_of_issue.is_empty() {
-            if let Some(vis_issue) = &visual_date_of_issue {
-                if let Some(parsed_date) = Self::parse_visual_date(vis_issue) {
-                    date_of_issue = parsed_date;
-                }
-            }
+        let date_of_expiry = [(Some(expiry_text), "expiry"), (visual_date_of_expiry, "expiry_date")]
+            .iter()
+            .find_map(|(d, key)| {
+                let date = Self::parse_visual_date(d.as_deref()?, |yy, month, day| {
+                    policy.resolve_year(DateKind::Expiry, yy, month, day)
+                })?;
+                use_capture(key);
+                Some(date)
+            })
+            .unwrap_or_else(|| mrz_data.date_of_expiry.clone());
+        let expiry = date_of_expiry.to_naive_date();
+        let date_of_issue = [(Some(issue_text), "issue"), (visual_date_of_issue, "issue_date")]
+            .iter()
+            .find_map(|(d, key)| {
+                let date = Self::parse_visual_date(d.as_deref()?, |yy, month, day| {
+                    policy.resolve_issue_year(yy, month, day, expiry)
+                })?;
+                use_capture(key);
+                Some(date)
+            })
+            .unwrap_or_default();
+        if place_of_birth.is_some() {
+            use_capture("place");
+        }
+        if authority.is_some() {
+            use_capture("authority");
         }
         
         // Core fields prepopulated from MRZ, optional fields may be empty if not found
-        let mut additional_fields = std::collections::HashMap::new();
+        let mut additional_fields = HashMap::new();
         additional_fields.insert("passport_type".to_string(), "P".to_string());
         
         Ok(VisualData {
-            document_type: "P".to_string(),
+            document_type: mrz_data.document_type.clone(),
             issuing_country,
             document_number,
             name: format!("{} {}", surname.trim(), given_names.trim()),
@@ -574,17 +581,18 @@ impl OcrProcessor {
             given_names,
             nationality,
             date_of_birth,
-            gender,
+            sex,
             place_of_birth,
             date_of_issue,
             date_of_expiry,
             authority,
             personal_number,
-            document_format: Some(DocumentFormat::TD3),
+            document_format: mrz_data.document_format.clone(),
             portrait: None,
             signature: None,
             secondary_portrait: None,
             additional_fields,
+            confidences,
         })
     }
 
@@ -592,108 +600,68 @@ impl OcrProcessor {
     // Removed unused format_mrz_date and format_mrz_expiry_date functions
     // They have been replaced by the more robust format_mrz_date_for_display function
 
-    // Add a specialized MRZ date parser that's more tolerant of OCR errors
-    fn parse_mrz_date(date_str: &str) -> Option<String> {
-        println!("Parsing MRZ date: {}", date_str);
-        
-        // Additional cleaning for MRZ dates - more aggressive than visual dates
-        let cleaned: String = date_str.chars()
-            .map(|c| match c {
-                'O' | 'Q' | 'D' | 'C' => '0', // Added 'C' as it's often confused with '0'
-                'I' | 'L' | '|' | '!' | 'l' | 'i' => '1', // Added more common confusions
-                'Z' | 'z' => '2',
-                'E' | 'e' => '3',
-                'A' | 'a' => '4',
-                'S' | 's' => '5',
-                'G' | 'b' => '6',
-                'T' | 't' => '7',
-                'B' | 'R' => '8',
-                'g' | 'q' => '9',
-                c if c.is_digit(10) => c,
-                _ => '0', // Replace any non-digit with '0' for MRZ
-            })
-            .collect();
-            
-        println!("MRZ cleaned date: {}", cleaned);
-        
-        // For MRZ, we expect exactly 6 digits in YYMMDD format
-        if cleaned.len() == 6 {
-            // Extract components with fallbacks
-            let yy = cleaned[0..2].parse::<u32>().unwrap_or(0);
-            let mm = cleaned[2..4].parse::<u32>().unwrap_or(1); // Default to January if invalid
-            let dd = cleaned[4..6].parse::<u32>().unwrap_or(1); // Default to 1st if invalid
-            
-            // Normalize month and day to valid ranges
-            let month = if mm < 1 || mm > 12 { 1 } else { mm };
-            let day = if dd < 1 || dd > 31 { 1 } else { dd };
-            
-            // For MRZ, we always return in YYMMDD format
-            return Some(format!("{:02}{:02}{:02}", yy, month, day));
-        } else if cleaned.len() > 6 {
-            // Try to extract 6 digits from a longer string
-            let digits_only: String = cleaned.chars().filter(|c| c.is_digit(10)).collect();
-            if digits_only.len() >= 6 {
-                // Take the first 6 digits
-                let yy = digits_only[0..2].parse::<u32>().unwrap_or(0);
-                let mm = digits_only[2..4].parse::<u32>().unwrap_or(1);
-                let dd = digits_only[4..6].parse::<u32>().unwrap_or(1);
-                
-                let month = if mm < 1 || mm > 12 { 1 } else { mm };
-                let day = if dd < 1 || dd > 31 { 1 } else { dd };
-                
-                return Some(format!("{:02}{:02}{:02}", yy, month, day));
-            }
-        }
-        
-        // Fallback to standard date parser
-        Self::parse_visual_date(&cleaned)
-    }
-    
     // Format MRZ date from YYMMDD format to a human-readable DD MM YYYY format
     // Removed unused format_mrz_date and format_mrz_expiry_date functions
     // They have been replaced by the more robust format_mrz_date_for_display function
 
-    fn parse_visual_date(date_str: &str) -> Option<String> {
+    /// Parse a VIZ date such as "12/08/1974", "12.08.74" or "19740812", where `XX` marks an
+    /// unknown day or month. Two-digit years are resolved by `resolve_year(yy, month, day)`.
+    fn parse_visual_date(
+        date_str: &str,
+        resolve_year: impl Fn(u32, u32, u32) -> Option<i32>,
+    ) -> Option<DocumentDate> {
         println!("Parsing date: {}", date_str); // Log input string
-        // Clean the string: keep only digits and common separators
-        let cleaned: String = date_str.chars().filter(|c| c.is_digit(10) || *c == '/' || *c == '-' || *c == '.').collect();
+        // Clean the string: keep only digits, unknown markers and common separators
+        let cleaned: String = date_str
+            .to_uppercase()
+            .chars()
+            .filter(|c| c.is_ascii_digit() || matches!(c, '/' | '-' | '.' | ' ' | 'X'))
+            .collect();
         println!("Cleaned string: {}", cleaned); // Log cleaned string
-        // Try regex with separators
-        let re_separated = Regex::new(r"(\d{1,2})\s*[/. -]\s*(\d{1,2})\s*[/. -]\s*(\d{2,4})").unwrap();
-        if let Some(cap) = re_separated.captures(&cleaned) {
-            println!("Capture groups (separated): {}, {}, {}", &cap[1], &cap[2], &cap[3]);
-            if let (Ok(num1), Ok(num2), Ok(num3)) = (cap[1].parse::<u32>(), cap[2].parse::<u32>(), cap[3].parse::<u32>()) {
-                let (day, month, year) = if num2 >= 1 && num2 <= 12 { (num1, num2, num3) } else if num1 >= 1 && num1 <= 12 { (num2, num1, num3) } else { return None; };
-                if (1..=31).contains(&day) && (1..=12).contains(&month) && (1900..=2100).contains(&year) {
-                    let yy = (year % 100) as u32;
-                    return Some(format!("{:02}{:02}{:02}", yy, month, day));
-                }
-            }
-        } else {
-            // Try contiguous digit string, e.g., YYMMDD or YYYYMMDD
-            let re_contiguous = Regex::new(r"(\d{6,8})").unwrap();
-            if let Some(cap) = re_contiguous.captures(&cleaned) {
-                let digits = &cap[1];
-                println!("Contiguous digits found: {}", digits); // Log contiguous digits
-                let len = digits.len();
-                if len == 6 {
-                    if let (Ok(yy), Ok(mm), Ok(dd)) = (digits[0..2].parse::<u32>(), digits[2..4].parse::<u32>(), digits[4..6].parse::<u32>()) {
-                        if (1..=31).contains(&dd) && (1..=12).contains(&mm) && yy <= 99 {
-                            return Some(format!("{:02}{:02}{:02}", yy, mm, dd));
-                        }
-                    }
-                } else if len == 8 {
-                    if let (Ok(year), Ok(mm), Ok(dd)) = (digits[0..4].parse::<u32>(), digits[4..6].parse::<u32>(), digits[6..8].parse::<u32>()) {
-                        if (1..=31).contains(&dd) && (1..=12).contains(&mm) && (1900..=2100).contains(&year) {
-                            let yy = year % 100;
-                            return Some(format!("{:02}{:02}{:02}", yy, mm, dd));
-                        }
-                    }
-                }
+
+        let (day, month, year) = if let Some(cap) = Regex::new(r"(\d{1,2}|XX)\s*[/. -]\s*(\d{1,2}|XX)\s*[/. -]\s*(\d{4}|\d{2})")
+            .unwrap()
+            .captures(&cleaned)
+        {
+            let first = cap[1].parse::<u32>().ok();
+            let second = cap[2].parse::<u32>().ok();
+            // Day first, unless only month first gives a valid month
+            let is_month = |value: Option<u32>| value.is_none_or(|m| (1..=12).contains(&m));
+            if is_month(second) {
+                (first, second, cap[3].to_string())
+            } else if is_month(first) {
+                (second, first, cap[3].to_string())
             } else {
-                println!("No date pattern found in cleaned string");
+                return None;
             }
+        } else if let Some(cap) = Regex::new(r"(\d{6,8})").unwrap().captures(&cleaned) {
+            // Contiguous digit string: YYMMDD or YYYYMMDD
+            let digits = &cap[1];
+            println!("Contiguous digits found: {}", digits); // Log contiguous digits
+            let year_len = match digits.len() {
+                6 => 2,
+                8 => 4,
+                _ => return None,
+            };
+            let (year, rest) = digits.split_at(year_len);
+            (rest[2..4].parse().ok(), rest[0..2].parse().ok(), year.to_string())
+        } else {
+            println!("No date pattern found in cleaned string");
+            return None;
+        };
+
+        if day.is_some_and(|d| !(1..=31).contains(&d)) || month.is_some_and(|m| !(1..=12).contains(&m)) {
+            return None;
         }
-        None
+        let year = match year.len() {
+            4 => Some(year.parse::<i32>().ok()?).filter(|y| (1900..=2100).contains(y))?,
+            _ => resolve_year(year.parse().ok()?, month.unwrap_or(1), day.unwrap_or(1))?,
+        };
+
+        let date = DocumentDate::new(Some(year), month, day, date_str.trim());
+        if date.is_complete() && date.to_naive_date().is_none() {
+            return None;
+        }
+        Some(date)
     }
 }
@@ -0,0 +1,302 @@
+use image::imageops::{self, FilterType};
+use image::GrayImage;
+use imageproc::contrast::otsu_level;
+use imageproc::region_labelling::{connected_components, Connectivity};
+use std::collections::HashMap;
+
+use crate::processing::ImageProcessor;
+
+/// A grayscale image turned so that its text reads left to right with the MRZ at the bottom
+#[derive(Debug, Clone)]
+pub struct OrientedImage {
+    pub image: GrayImage,
+    /// Clockwise rotation applied to the input: 0, 90, 180 or 270 degrees
+    pub rotation: u32,
+}
+
+/// Detects documents that were scanned sideways or upside down.
+///
+/// The MRZ is the main cue. Its band is only found when its lines run horizontally, and it is
+/// printed along the bottom edge, on passport data pages as well as on the back of ID cards. The
+/// image is searched in all four orientations, each yielding the lowest wide band of text, and the
+/// orientation whose band looks most like an MRZ (two or three evenly spaced lines running across
+/// the whole band) wins, so a wide line of text below an upside-down MRZ is not mistaken for it.
+/// Without an MRZ, sideways pages are still recognized because text lines make the ink projected
+/// onto rows far more uneven than the ink projected onto columns. Up is then told from down by the
+/// shape of the lines: Latin glyphs share a baseline while their tops vary with ascenders and
+/// capitals, and lines start at a common left margin while their right ends are ragged.
+pub struct OrientationDetector;
+
+impl OrientationDetector {
+    /// Images are analyzed at this size (longest side) at most
+    const ANALYSIS_SIZE: u32 = 1000;
+    /// Without an MRZ, columns must be this much more uneven than rows to turn the page
+    const MIN_PROFILE_RATIO: f64 = 1.2;
+    /// Without an MRZ, upright text must score below minus this to turn the page over
+    const MIN_UPSIDE_DOWN_SCORE: f64 = 0.2;
+    /// Glyphs are at least this many pixels tall at the analysis size
+    const MIN_GLYPH_HEIGHT: u32 = 4;
+    /// and at most this share of the image height
+    const MAX_GLYPH_SHARE: f64 = 0.06;
+    /// A run of text needs this many glyphs to take part in the up/down vote
+    const MIN_LINE_GLYPHS: usize = 4;
+    /// Rows with at least 1/n of the ink of the densest row belong to a line of text
+    const LINE_ROW_SHARE: u32 = 5;
+    /// Added to the MRZ signature of a band in the bottom half of the page
+    const BOTTOM_BONUS: f64 = 0.1;
+
+    /// Detect the orientation of the image and turn it upright
+    pub fn orient(img: &GrayImage) -> OrientedImage {
+        let rotation = Self::detect(img);
+        OrientedImage {
+            image: Self::rotate(img, rotation),
+            rotation,
+        }
+    }
+
+    /// Clockwise rotation (0, 90, 180 or 270 degrees) that turns the image upright
+    pub fn detect(img: &GrayImage) -> u32 {
+        if img.width() == 0 || img.height() == 0 {
+            return 0;
+        }
+        let scale = (img.width().max(img.height()) as f32 / Self::ANALYSIS_SIZE as f32).max(1.0);
+        let small = imageops::resize(
+            img,
+            ((img.width() as f32 / scale).round() as u32).max(1),
+            ((img.height() as f32 / scale).round() as u32).max(1),
+            FilterType::Triangle,
+        );
+
+        let best = [0, 180, 90, 270]
+            .into_iter()
+            .filter_map(|rotation| {
+                let candidate = Self::rotate(&small, rotation);
+                let bounds = ImageProcessor::locate_mrz(&candidate)?.bounds;
+                let band = imageops::crop_imm(&candidate, bounds.x, bounds.y, bounds.width, bounds.height).to_image();
+                // Between bands that look alike, the one along the bottom edge is the MRZ
+                let at_bottom = bounds.y + bounds.height / 2 >= candidate.height() / 2;
+                let score = Self::mrz_signature(&band) + if at_bottom { Self::BOTTOM_BONUS } else { 0.0 };
+                Some((rotation, score))
+            })
+            .fold(None, |best: Option<(u32, f64)>, candidate| match best {
+                Some(best) if best.1 >= candidate.1 => Some(best),
+                _ => Some(candidate),
+            });
+        if let Some((rotation, _)) = best {
+            return rotation;
+        }
+
+        let (rows, columns) = Self::profile_unevenness(&small);
+        if columns >= rows * Self::MIN_PROFILE_RATIO {
+            // Sideways: one of the two quarter turns reads upright
+            if Self::upright_score(&Self::rotate(&small, 90)) >= 0.0 {
+                90
+            } else {
+                270
+            }
+        } else if Self::upright_score(&small) < -Self::MIN_UPSIDE_DOWN_SCORE {
+            180
+        } else {
+            0
+        }
+    }
+
+    /// Evidence that horizontal text reads upright (positive) rather than upside down
+    /// (negative), from -2 to 2: whether glyphs in a line align on their bottoms or their
+    /// tops, and whether lines align on their left or their right ends
+    fn upright_score(img: &GrayImage) -> f64 {
+        let threshold = otsu_level(img);
+        let ink = GrayImage::from_fn(img.width(), img.height(), |x, y| {
+            image::Luma([if img.get_pixel(x, y)[0] <= threshold { 255 } else { 0 }])
+        });
+        let labels = connected_components(&ink, Connectivity::Eight, image::Luma([0]));
+
+        // Bounding boxes (left, top, right, bottom) of each component
+        let mut boxes: HashMap<u32, (u32, u32, u32, u32)> = HashMap::new();
+        for (x, y, label) in labels.enumerate_pixels() {
+            if label[0] == 0 {
+                continue;
+            }
+            let b = boxes.entry(label[0]).or_insert((x, y, x, y));
+            *b = (b.0.min(x), b.1.min(y), b.2.max(x), b.3.max(y));
+        }
+        let max_height = (img.height() as f64 * Self::MAX_GLYPH_SHARE) as u32;
+        let mut glyphs: Vec<(u32, u32, u32, u32)> = boxes
+            .into_values()
+            .filter(|(l, t, r, b)| {
+                let (width, height) = (r - l + 1, b - t + 1);
+                (Self::MIN_GLYPH_HEIGHT..=max_height).contains(&height) && width <= height * 3
+            })
+            .collect();
+        if glyphs.len() < Self::MIN_LINE_GLYPHS {
+            return 0.0;
+        }
+        let mut heights: Vec<u32> = glyphs.iter().map(|(_, t, _, b)| b - t + 1).collect();
+        heights.sort_unstable();
+        let glyph_height = heights[heights.len() / 2] as f64;
+
+        // Group glyphs into lines by their vertical centre, then split lines at wide gaps so
+        // that columns of text are measured separately
+        glyphs.sort_by_key(|(_, t, _, b)| t + b);
+        let mut lines: Vec<Vec<(u32, u32, u32, u32)>> = Vec::new();
+        for glyph in glyphs {
+            let centre = (glyph.1 + glyph.3) as f64 / 2.0;
+            match lines.last_mut() {
+                Some(line) if (centre - (line[0].1 + line[0].3) as f64 / 2.0).abs() <= glyph_height / 2.0 => {
+                    line.push(glyph)
+                }
+                _ => lines.push(vec![glyph]),
+            }
+        }
+        let mut segments: Vec<Vec<(u32, u32, u32, u32)>> = Vec::new();
+        for mut line in lines {
+            line.sort_by_key(|(l, ..)| *l);
+            let mut segment: Vec<(u32, u32, u32, u32)> = Vec::new();
+            for glyph in line {
+                if let Some(last) = segment.last() {
+                    if glyph.0 as f64 - last.2 as f64 > glyph_height * 2.0 {
+                        segments.push(std::mem::take(&mut segment));
+                    }
+                }
+                segment.push(glyph);
+            }
+            segments.push(segment);
+        }
+        segments.retain(|segment| segment.len() >= Self::MIN_LINE_GLYPHS);
+        if segments.is_empty() {
+            return 0.0;
+        }
+
+        // Tops vary more than bottoms in upright text
+        let deviation = |values: Vec<f64>| {
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            values.iter().map(|v| (v - mean).abs()).sum::<f64>() / values.len() as f64
+        };
+        let baseline = segments
+            .iter()
+            .map(|segment| {
+                let tops = deviation(segment.iter().map(|g| g.1 as f64).collect());
+                let bottoms = deviation(segment.iter().map(|g| g.3 as f64).collect());
+                ((tops - bottoms) / glyph_height).clamp(-1.0, 1.0)
+            })
+            .sum::<f64>()
+            / segments.len() as f64;
+
+        // Line starts line up more often than line ends in upright text
+        let extents: Vec<(f64, f64)> = segments
+            .iter()
+            .map(|segment| (segment[0].0 as f64, segment[segment.len() - 1].2 as f64))
+            .collect();
+        let (mut starts, mut ends, mut pairs) = (0i64, 0i64, 0i64);
+        for (i, a) in extents.iter().enumerate() {
+            for b in &extents[i + 1..] {
+                pairs += 1;
+                starts += ((a.0 - b.0).abs() <= glyph_height) as i64;
+                ends += ((a.1 - b.1).abs() <= glyph_height) as i64;
+            }
+        }
+        let alignment = if pairs > 0 {
+            (starts - ends) as f64 / pairs as f64
+        } else {
+            0.0
+        };
+
+        baseline + alignment
+    }
+
+    /// How much a band of text looks like an MRZ, from 0 to 1: two or three lines of the same
+    /// height, evenly spaced, each running across the whole band
+    fn mrz_signature(band: &GrayImage) -> f64 {
+        let (width, height) = band.dimensions();
+        if width == 0 || height == 0 {
+            return 0.0;
+        }
+        let threshold = otsu_level(band);
+        let rows: Vec<u32> = (0..height)
+            .map(|y| (0..width).filter(|x| band.get_pixel(*x, y)[0] <= threshold).count() as u32)
+            .collect();
+        let max = rows.iter().copied().max().unwrap_or(0);
+        if max == 0 {
+            return 0.0;
+        }
+
+        // Lines are runs of rows with a fair share of the densest row's ink
+        let mut lines: Vec<(u32, u32)> = Vec::new();
+        let mut start = None;
+        for (y, count) in rows.iter().chain(std::iter::once(&0)).enumerate() {
+            match (start, *count * Self::LINE_ROW_SHARE >= max) {
+                (None, true) => start = Some(y as u32),
+                (Some(from), false) => {
+                    if y as u32 - from >= 2 {
+                        lines.push((from, y as u32));
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if !(2..=3).contains(&lines.len()) {
+            return 0.0;
+        }
+
+        let ratio = |values: &[f64]| {
+            let min = values.iter().copied().fold(f64::MAX, f64::min);
+            let max = values.iter().copied().fold(0.0, f64::max);
+            if max > 0.0 {
+                min / max
+            } else {
+                0.0
+            }
+        };
+        let heights: Vec<f64> = lines.iter().map(|(from, to)| (to - from) as f64).collect();
+        let gaps: Vec<f64> = lines.windows(2).map(|pair| (pair[1].0 - pair[0].0) as f64).collect();
+        let extents: Vec<f64> = lines
+            .iter()
+            .map(|(from, to)| {
+                let inked: Vec<u32> = (0..width)
+                    .filter(|x| (*from..*to).any(|y| band.get_pixel(*x, y)[0] <= threshold))
+                    .collect();
+                match (inked.first(), inked.last()) {
+                    (Some(first), Some(last)) => (last - first + 1) as f64 / width as f64,
+                    _ => 0.0,
+                }
+            })
+            .collect();
+        let extent = extents.iter().copied().fold(f64::MAX, f64::min);
+
+        ratio(&heights) * ratio(&gaps) * extent
+    }
+
+    /// Rotate clockwise by a multiple of 90 degrees
+    pub fn rotate(img: &GrayImage, rotation: u32) -> GrayImage {
+        match rotation % 360 {
+            90 => imageops::rotate90(img),
+            180 => imageops::rotate180(img),
+            270 => imageops::rotate270(img),
+            _ => img.clone(),
+        }
+    }
+
+    /// How uneven the ink is across rows and across columns: the sum of squared counts
+    /// relative to that of ink spread evenly, so 1 means no structure
+    fn profile_unevenness(img: &GrayImage) -> (f64, f64) {
+        let threshold = otsu_level(img);
+        let mut rows = vec![0u64; img.height() as usize];
+        let mut columns = vec![0u64; img.width() as usize];
+        for (x, y, pixel) in img.enumerate_pixels() {
+            if pixel[0] <= threshold {
+                rows[y as usize] += 1;
+                columns[x as usize] += 1;
+            }
+        }
+        let unevenness = |counts: &[u64]| {
+            let total: u64 = counts.iter().sum();
+            if total == 0 {
+                return 1.0;
+            }
+            let squares: u64 = counts.iter().map(|c| c * c).sum();
+            counts.len() as f64 * squares as f64 / (total as f64 * total as f64)
+        };
+        (unevenness(&rows), unevenness(&columns))
+    }
+}
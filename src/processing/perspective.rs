@@ -0,0 +1,286 @@
+use image::imageops::{self, FilterType};
+use image::{GrayImage, Luma};
+use imageproc::contours::{find_contours, BorderType};
+use imageproc::edges::canny;
+use imageproc::filter::gaussian_blur_f32;
+use imageproc::geometric_transformations::{warp_into, Interpolation, Projection};
+use imageproc::geometry::{approximate_polygon_dp, convex_hull};
+use imageproc::point::Point;
+
+use crate::models::DocumentFormat;
+use crate::processing::ImageProcessor;
+
+/// Corners of a document in an image: top left, top right, bottom right, bottom left
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DocumentQuad {
+    pub corners: [(f32, f32); 4],
+}
+
+impl DocumentQuad {
+    /// Mean length of the top and bottom edges, and of the left and right edges
+    pub fn size(&self) -> (f32, f32) {
+        let length = |a: (f32, f32), b: (f32, f32)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+        let [top_left, top_right, bottom_right, bottom_left] = self.corners;
+        (
+            (length(top_left, top_right) + length(bottom_left, bottom_right)) / 2.0,
+            (length(top_left, bottom_left) + length(top_right, bottom_right)) / 2.0,
+        )
+    }
+}
+
+/// A document warped to the canonical aspect ratio of its format
+#[derive(Debug, Clone)]
+pub struct NormalizedPage {
+    pub image: GrayImage,
+    /// Where the document was found in the input
+    pub quad: DocumentQuad,
+    /// Format whose dimensions the page was warped to
+    pub format: DocumentFormat,
+    /// Physical scale of the page
+    pub pixels_per_mm: f32,
+}
+
+/// Finds the document in a photo and removes the perspective distortion.
+///
+/// The document is the largest convex quadrilateral outlined by edges, which separates it from
+/// the table, fingers and background around it. Pages are warped to landscape, the orientation
+/// in which the MRZ runs along the bottom edge.
+pub struct PageNormalizer;
+
+impl PageNormalizer {
+    /// Images are searched for the document at this width
+    const ANALYSIS_WIDTH: u32 = 500;
+    /// The document covers at least this share of the image
+    const MIN_AREA_RATIO: f32 = 0.2;
+    /// Long to short side ratios accepted for a document; ICAO formats range from 1.42 to 1.59,
+    /// and the margin absorbs perspective while rejecting the portrait box (about 1.29)
+    const ASPECT_RATIO_RANGE: (f32, f32) = (1.33, 1.8);
+    /// Polygon simplification tolerances tried, as shares of the outline perimeter
+    const SIMPLIFICATION: [f64; 4] = [0.02, 0.03, 0.05, 0.08];
+    /// Aspect ratios within this relative difference are indistinguishable in a photo
+    const ASPECT_TOLERANCE: f32 = 0.03;
+    /// Formats tried when matching an aspect ratio, the most common first
+    const FORMATS: [DocumentFormat; 5] = [
+        DocumentFormat::TD3,
+        DocumentFormat::TD1,
+        DocumentFormat::TD2,
+        DocumentFormat::MRVA,
+        DocumentFormat::MRVB,
+    ];
+
+    /// Detect the document and warp it to the dimensions of the format, or of the format that
+    /// best matches its aspect ratio when none is given
+    pub fn normalize(img: &GrayImage, format: Option<&DocumentFormat>) -> Option<NormalizedPage> {
+        let quad = Self::detect(img)?;
+        let (width, height) = quad.size();
+        let format = format.cloned().unwrap_or_else(|| Self::match_format(width.max(height) / width.min(height)));
+        let (long_mm, short_mm) = Self::landscape_dimensions(&format);
+
+        // A portrait quad is a document turned sideways; start at the corner that makes the
+        // first edge a long one, so the page comes out in landscape
+        let corners = if height > width {
+            let [top_left, top_right, bottom_right, bottom_left] = quad.corners;
+            [bottom_left, top_left, top_right, bottom_right]
+        } else {
+            quad.corners
+        };
+
+        // Keep the resolution of the input along the long edge
+        let pixels_per_mm = width.max(height) / long_mm;
+        let target_width = (long_mm * pixels_per_mm).round().max(1.0);
+        let target_height = (short_mm * pixels_per_mm).round().max(1.0);
+        let target = [(0.0, 0.0), (target_width, 0.0), (target_width, target_height), (0.0, target_height)];
+        let projection = Projection::from_control_points(corners, target)?;
+
+        let mut image = GrayImage::new(target_width as u32, target_height as u32);
+        warp_into(img, &projection, Interpolation::Bilinear, Luma([255u8]), &mut image);
+        Some(NormalizedPage {
+            image,
+            quad,
+            format,
+            pixels_per_mm,
+        })
+    }
+
+    /// Find the corners of the document in the image
+    pub fn detect(img: &GrayImage) -> Option<DocumentQuad> {
+        if img.width() == 0 || img.height() == 0 {
+            return None;
+        }
+        let scale = img.width() as f32 / Self::ANALYSIS_WIDTH as f32;
+        let height = ((img.height() as f32 / scale).round() as u32).max(1);
+        let small = imageops::resize(img, Self::ANALYSIS_WIDTH, height, FilterType::Triangle);
+
+        // Close small gaps in the outline so it forms one contour
+        let edges = canny(&gaussian_blur_f32(&small, 2.0), 20.0, 60.0);
+        let edges = ImageProcessor::max_filter(&edges, 1, 1);
+
+        let min_area = Self::MIN_AREA_RATIO * (small.width() * small.height()) as f32;
+        let corners = find_contours::<i32>(&edges)
+            .into_iter()
+            .filter(|contour| contour.border_type == BorderType::Outer)
+            .filter_map(|contour| Self::quadrilateral(&contour.points))
+            .filter(|corners| {
+                let (width, height) = DocumentQuad { corners: *corners }.size();
+                let aspect_ratio = width.max(height) / width.min(height).max(1.0);
+                (Self::ASPECT_RATIO_RANGE.0..=Self::ASPECT_RATIO_RANGE.1).contains(&aspect_ratio)
+            })
+            .map(|corners| (Self::area(&corners), corners))
+            .filter(|(area, _)| *area >= min_area)
+            .max_by(|a, b| a.0.total_cmp(&b.0))?
+            .1;
+
+        Some(DocumentQuad {
+            corners: corners.map(|(x, y)| (x * scale, y * scale)),
+        })
+    }
+
+    /// The outline of a contour as four ordered corners, if it simplifies to a quadrilateral
+    fn quadrilateral(points: &[Point<i32>]) -> Option<[(f32, f32); 4]> {
+        let hull = convex_hull(points);
+        if hull.len() < 4 {
+            return None;
+        }
+        let perimeter: f64 = hull
+            .iter()
+            .zip(hull.iter().cycle().skip(1))
+            .map(|(a, b)| (((a.x - b.x).pow(2) + (a.y - b.y).pow(2)) as f64).sqrt())
+            .sum();
+        // The simplification drops the last point of a closed curve, which may be a corner, so
+        // split the outline at the point farthest from its start and simplify both halves
+        let start = hull[0];
+        let far = (1..hull.len()).max_by_key(|&i| (hull[i].x - start.x).pow(2) + (hull[i].y - start.y).pow(2))?;
+        let back: Vec<Point<i32>> = hull[far..].iter().chain(std::iter::once(&start)).copied().collect();
+        let polygon = Self::SIMPLIFICATION
+            .iter()
+            .map(|tolerance| {
+                let mut polygon = approximate_polygon_dp(&hull[..=far], tolerance * perimeter, false);
+                // Both halves include the far point, and the second one ends at the start again
+                polygon.pop();
+                polygon.extend(approximate_polygon_dp(&back, tolerance * perimeter, false));
+                polygon.pop();
+                polygon
+            })
+            .find(|polygon| polygon.len() == 4)?;
+
+        // Top left has the smallest x + y, bottom right the largest; top right has the largest
+        // x - y and bottom left the smallest
+        let points: Vec<(f32, f32)> = polygon.iter().map(|p| (p.x as f32, p.y as f32)).collect();
+        let pick = |key: fn(&(f32, f32)) -> f32, largest: bool| {
+            let ordered = points.iter().copied();
+            if largest {
+                ordered.max_by(|a, b| key(a).total_cmp(&key(b)))
+            } else {
+                ordered.min_by(|a, b| key(a).total_cmp(&key(b)))
+            }
+        };
+        let sum = |p: &(f32, f32)| p.0 + p.1;
+        let difference = |p: &(f32, f32)| p.0 - p.1;
+        Some([
+            pick(sum, false)?,
+            pick(difference, true)?,
+            pick(sum, true)?,
+            pick(difference, false)?,
+        ])
+    }
+
+    /// Area of a polygon (shoelace formula)
+    fn area(corners: &[(f32, f32); 4]) -> f32 {
+        let twice: f32 = (0..4)
+            .map(|i| {
+                let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                a.0 * b.1 - b.0 * a.1
+            })
+            .sum();
+        twice.abs() / 2.0
+    }
+
+    /// The format whose long to short side ratio is closest to the measured one, preferring
+    /// the more common formats among those within the tolerance
+    fn match_format(aspect_ratio: f32) -> DocumentFormat {
+        let difference = |format: &DocumentFormat| {
+            let (long, short) = Self::landscape_dimensions(format);
+            ((long / short) - aspect_ratio).abs() / aspect_ratio
+        };
+        Self::FORMATS
+            .iter()
+            .find(|format| difference(format) <= Self::ASPECT_TOLERANCE)
+            .or_else(|| Self::FORMATS.iter().min_by(|a, b| difference(a).total_cmp(&difference(b))))
+            .cloned()
+            .unwrap_or(DocumentFormat::TD3)
+    }
+
+    /// Long and short side of a format in millimetres
+    fn landscape_dimensions(format: &DocumentFormat) -> (f32, f32) {
+        let (width, height) = format.dimensions();
+        (width.max(height), width.min(height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A light document of the given size on a dark background
+    fn photo(width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width + 200, height + 200, |x, y| {
+            let inside = (100..100 + width).contains(&x) && (100..100 + height).contains(&y);
+            Luma([if inside { 230 } else { 40 }])
+        })
+    }
+
+    fn near(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() <= 6.0 && (a.1 - b.1).abs() <= 6.0
+    }
+
+    #[test]
+    fn orders_corners_clockwise_from_top_left() {
+        // Outline of a slightly rotated quad traced from its bottom right corner
+        let outline = [(310, 200), (300, 20), (20, 10), (10, 210), (310, 200)];
+        let points: Vec<Point<i32>> = outline
+            .windows(2)
+            .flat_map(|edge| {
+                let ((x0, y0), (x1, y1)) = (edge[0], edge[1]);
+                (0..20).map(move |i| Point::new(x0 + (x1 - x0) * i / 20, y0 + (y1 - y0) * i / 20))
+            })
+            .collect();
+        let corners = PageNormalizer::quadrilateral(&points).expect("quadrilateral");
+        assert_eq!(corners, [(20.0, 10.0), (300.0, 20.0), (310.0, 200.0), (10.0, 210.0)]);
+    }
+
+    #[test]
+    fn matches_formats_by_aspect_ratio() {
+        // TD3 (125 x 88) and MRV-B (105 x 74) cannot be told apart; the more common TD3 wins
+        assert_eq!(PageNormalizer::match_format(125.0 / 88.0), DocumentFormat::TD3);
+        assert_eq!(PageNormalizer::match_format(105.0 / 74.0), DocumentFormat::TD3);
+        assert_eq!(PageNormalizer::match_format(85.6 / 54.0), DocumentFormat::TD1);
+        assert_eq!(PageNormalizer::match_format(120.0 / 80.0), DocumentFormat::MRVA);
+    }
+
+    #[test]
+    fn detects_the_document_outline() {
+        let quad = PageNormalizer::detect(&photo(625, 440)).expect("document found");
+        let expected = [(100.0, 100.0), (725.0, 100.0), (725.0, 540.0), (100.0, 540.0)];
+        for (corner, expected) in quad.corners.iter().zip(expected) {
+            assert!(near(*corner, expected), "{:?} is not near {:?}", corner, expected);
+        }
+        assert!(PageNormalizer::detect(&GrayImage::from_pixel(400, 300, Luma([200]))).is_none());
+    }
+
+    #[test]
+    fn warps_to_the_format_dimensions() {
+        let page = PageNormalizer::normalize(&photo(625, 440), None).expect("page normalized");
+        assert_eq!(page.format, DocumentFormat::TD3);
+        assert!((page.pixels_per_mm - 5.0).abs() < 0.1);
+
+        // A given format is used even when another matches the aspect ratio as well
+        let page = PageNormalizer::normalize(&photo(625, 440), Some(&DocumentFormat::MRVB)).expect("page normalized");
+        assert_eq!(page.format, DocumentFormat::MRVB);
+        let ratio = page.image.width() as f32 / page.image.height() as f32;
+        assert!((ratio - 105.0 / 74.0).abs() < 0.01);
+
+        // A document turned sideways comes out in landscape
+        let page = PageNormalizer::normalize(&photo(440, 625), None).expect("page normalized");
+        assert!(page.image.width() > page.image.height());
+    }
+}
@@ -0,0 +1,430 @@
+use image::imageops::{self, FilterType};
+use image::{GrayImage, ImageBuffer, Luma};
+use imageproc::contrast::otsu_level;
+use imageproc::region_labelling::{connected_components, Connectivity};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::processing::{BoundingBox, DocumentQuad, ImageProcessor, ImageTransform, PageNormalizer, ProcessedImage};
+use crate::utils::PassportError;
+
+/// Limits an image must meet to be worth reading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityThresholds {
+    /// Minimum variance of the Laplacian over the MRZ (or the whole image without one)
+    pub min_sharpness: f64,
+    /// Maximum share of the MRZ covered by specular hotspots
+    pub max_glare: f32,
+    /// Minimum resolution; scans should be at least 300 DPI
+    pub min_dpi: u32,
+    /// The brightest pixels (95th percentile) must reach this level, or the paper is too dark
+    pub min_highlight_level: u8,
+    /// The darkest pixels (5th percentile) must stay below this level, or the text is washed out
+    pub max_shadow_level: u8,
+    /// Report images in which the outline of the document cannot be found as cropped. Off by
+    /// default, since flatbed scans are usually cut to the document edge; images whose MRZ
+    /// spans nearly their full width are taken to be cut to the document either way
+    pub require_document_outline: bool,
+}
+
+impl Default for QualityThresholds {
+    fn default() -> Self {
+        QualityThresholds {
+            min_sharpness: 100.0,
+            max_glare: 0.02,
+            min_dpi: 300,
+            min_highlight_level: 100,
+            max_shadow_level: 140,
+            require_document_outline: false,
+        }
+    }
+}
+
+/// A reason to retake an image rather than run OCR on it
+#[derive(Debug, Clone, PartialEq)]
+pub enum QualityIssue {
+    Blurry { sharpness: f64, minimum: f64 },
+    Glare { share: f32, maximum: f32 },
+    LowResolution { dpi: u32, minimum: u32 },
+    Underexposed { highlight_level: u8 },
+    Overexposed { shadow_level: u8 },
+    Cropped(Cropping),
+}
+
+/// The part of the document a cropped image is missing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cropping {
+    /// Characters of the MRZ run into the edge of the image
+    MrzCut,
+    /// No MRZ was found, e.g. because it lies outside the image
+    MrzMissing,
+    /// The outline of the document was not found, so some of its edges are outside the image
+    DocumentOutlineMissing,
+}
+
+impl fmt::Display for QualityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QualityIssue::Blurry { sharpness, minimum } => write!(
+                f,
+                "Image is too blurry (sharpness {:.0}, minimum {:.0}); hold the camera steady and retake",
+                sharpness, minimum
+            ),
+            QualityIssue::Glare { share, maximum } => write!(
+                f,
+                "Glare covers {:.1}% of the MRZ (maximum {:.1}%); tilt the document away from the light and retake",
+                share * 100.0,
+                maximum * 100.0
+            ),
+            QualityIssue::LowResolution { dpi, minimum } => write!(
+                f,
+                "Resolution is {} DPI, below the required {} DPI; move closer or scan at a higher resolution",
+                dpi, minimum
+            ),
+            QualityIssue::Underexposed { highlight_level } => write!(
+                f,
+                "Image is too dark (brightest areas at {}); add light and retake",
+                highlight_level
+            ),
+            QualityIssue::Overexposed { shadow_level } => write!(
+                f,
+                "Image is overexposed (darkest areas at {}); reduce the light and retake",
+                shadow_level
+            ),
+            QualityIssue::Cropped(Cropping::MrzCut) => {
+                write!(f, "The MRZ is cut off at the edge of the image; capture the whole document")
+            }
+            QualityIssue::Cropped(Cropping::MrzMissing) => {
+                write!(f, "No MRZ was found in the image; capture the whole document, MRZ included")
+            }
+            QualityIssue::Cropped(Cropping::DocumentOutlineMissing) => write!(
+                f,
+                "The edges of the document are not all in the image; capture the whole document with a margin around it"
+            ),
+        }
+    }
+}
+
+/// Quality measurements of an image and the issues found against the thresholds
+#[derive(Debug, Clone)]
+pub struct ImageQuality {
+    /// Variance of the Laplacian; sharp text scores high, blurred text low
+    pub sharpness: f64,
+    /// Share of the MRZ (or of the bottom quarter of the image without one) covered by
+    /// specular hotspots
+    pub glare: f32,
+    /// Resolution measured on the document when the page was normalized, otherwise estimated
+    /// from the width of the MRZ; the density recorded in the file is only used without either,
+    /// since editors often write a meaningless 72 DPI
+    pub dpi: Option<u32>,
+    /// Gray level of the darkest 5% of the pixels
+    pub shadow_level: u8,
+    /// Gray level of the brightest 5% of the pixels
+    pub highlight_level: u8,
+    /// Where the MRZ band was found, if at all
+    pub mrz_bounds: Option<BoundingBox>,
+    /// Whether the outline of the document was found, in this image or before the page was
+    /// normalized
+    pub document_found: bool,
+    pub issues: Vec<QualityIssue>,
+}
+
+impl ImageQuality {
+    pub fn is_acceptable(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// The report when no issue was found, otherwise an `ImageQualityError` listing every issue
+    pub fn ensure_acceptable(self) -> Result<Self, PassportError> {
+        if self.is_acceptable() {
+            return Ok(self);
+        }
+        let issues: Vec<String> = self.issues.iter().map(|issue| issue.to_string()).collect();
+        Err(PassportError::ImageQualityError(issues.join("; ")))
+    }
+}
+
+/// Measures whether an image is good enough for OCR, in a fraction of the time OCR takes
+pub struct QualityAnalyzer;
+
+impl QualityAnalyzer {
+    /// Sharpness is measured at this width at most, so that it doesn't depend on resolution
+    const SHARPNESS_WIDTH: u32 = 1000;
+    /// Pixels at or above this level are saturated
+    const SATURATED: u8 = 250;
+    /// Glare is looked for at this width at most
+    const GLARE_ANALYSIS_WIDTH: u32 = 256;
+    /// Saturated areas count as glare when the paper around them is at least this much darker
+    const GLARE_CONTRAST: u8 = 15;
+    /// Width of the ring around a saturated area whose paper is its background, in analysis pixels
+    const HOTSPOT_RING: u32 = 8;
+    /// Saturated areas covering more of the image than this are paper, not reflections
+    const MAX_HOTSPOT_AREA: f32 = 0.25;
+    /// Share of ink along an edge of the image above which characters are cut
+    const CROPPED_INK_SHARE: f32 = 0.05;
+    /// Document corners closer to the image edge than this share of its size are on the edge
+    const EDGE_MARGIN: f32 = 0.01;
+    /// An MRZ spanning this share of the image width means the image is cut to the document
+    const CUT_TO_DOCUMENT_MRZ_SHARE: f32 = 0.8;
+    /// Width of MRZ characters: OCR-B is printed at 10 characters per inch
+    const MRZ_CHARACTERS_PER_INCH: f32 = 10.0;
+
+    pub fn assess(image: &ProcessedImage, thresholds: &QualityThresholds) -> Result<ImageQuality, PassportError> {
+        let gray = image.to_gray()?;
+        let mrz = ImageProcessor::locate_mrz(&gray);
+        let mrz_bounds = mrz.as_ref().map(|region| region.bounds);
+        let focus = match &mrz_bounds {
+            Some(bounds) => imageops::crop_imm(&gray, bounds.x, bounds.y, bounds.width, bounds.height).to_image(),
+            None => gray.clone(),
+        };
+
+        let sharpness = Self::sharpness(&focus);
+        // Without an MRZ, look where it is printed on an upright page: along the bottom
+        let glare_region = mrz_bounds.unwrap_or(BoundingBox {
+            x: 0,
+            y: gray.height() * 3 / 4,
+            width: gray.width(),
+            height: gray.height() - gray.height() * 3 / 4,
+        });
+        let glare = Self::glare(&gray, &glare_region);
+        let dpi = Self::measured_dpi(image)
+            .or_else(|| mrz_bounds.map(|bounds| Self::estimate_dpi(&bounds)))
+            .or(image.dpi);
+        let (shadow_level, highlight_level) = Self::percentiles(&gray);
+        let mrz_cut = mrz_bounds.is_some_and(|bounds| Self::is_cut(&gray, &focus, &bounds));
+        let document_found = image
+            .transforms
+            .iter()
+            .any(|transform| matches!(transform, ImageTransform::PerspectiveWarp { .. }))
+            || PageNormalizer::detect(&gray).is_some_and(|quad| Self::is_inside(&gray, &quad));
+
+        let mut issues = Vec::new();
+        if sharpness < thresholds.min_sharpness {
+            issues.push(QualityIssue::Blurry {
+                sharpness,
+                minimum: thresholds.min_sharpness,
+            });
+        }
+        if glare > thresholds.max_glare {
+            issues.push(QualityIssue::Glare {
+                share: glare,
+                maximum: thresholds.max_glare,
+            });
+        }
+        if let Some(dpi) = dpi.filter(|dpi| *dpi < thresholds.min_dpi) {
+            issues.push(QualityIssue::LowResolution {
+                dpi,
+                minimum: thresholds.min_dpi,
+            });
+        }
+        if highlight_level < thresholds.min_highlight_level {
+            issues.push(QualityIssue::Underexposed { highlight_level });
+        }
+        if shadow_level > thresholds.max_shadow_level {
+            issues.push(QualityIssue::Overexposed { shadow_level });
+        }
+        if mrz_bounds.is_none() {
+            issues.push(QualityIssue::Cropped(Cropping::MrzMissing));
+        } else if mrz_cut {
+            issues.push(QualityIssue::Cropped(Cropping::MrzCut));
+        }
+        let cut_to_document = mrz_bounds
+            .is_some_and(|bounds| bounds.width as f32 >= gray.width() as f32 * Self::CUT_TO_DOCUMENT_MRZ_SHARE);
+        if thresholds.require_document_outline && !document_found && !cut_to_document {
+            issues.push(QualityIssue::Cropped(Cropping::DocumentOutlineMissing));
+        }
+
+        Ok(ImageQuality {
+            sharpness,
+            glare,
+            dpi,
+            shadow_level,
+            highlight_level,
+            mrz_bounds,
+            document_found,
+            issues,
+        })
+    }
+
+    /// Variance of the 4-neighbour Laplacian
+    fn sharpness(img: &GrayImage) -> f64 {
+        let img = if img.width() > Self::SHARPNESS_WIDTH {
+            let height = ((img.height() as u64 * Self::SHARPNESS_WIDTH as u64 / img.width() as u64) as u32).max(1);
+            imageops::resize(img, Self::SHARPNESS_WIDTH, height, FilterType::Triangle)
+        } else {
+            img.clone()
+        };
+        let (width, height) = img.dimensions();
+        if width < 3 || height < 3 {
+            return 0.0;
+        }
+
+        let value = |x: u32, y: u32| img.get_pixel(x, y)[0] as f64;
+        let mut sum = 0.0;
+        let mut squares = 0.0;
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                let laplacian =
+                    value(x - 1, y) + value(x + 1, y) + value(x, y - 1) + value(x, y + 1) - 4.0 * value(x, y);
+                sum += laplacian;
+                squares += laplacian * laplacian;
+            }
+        }
+        let count = ((width - 2) * (height - 2)) as f64;
+        let mean = sum / count;
+        squares / count - mean * mean
+    }
+
+    /// Share of the region covered by specular hotspots.
+    ///
+    /// Text is removed with a dilation, leaving the paper level, and every saturated area is
+    /// compared with the paper in a ring around it: a hotspot is brighter than its own
+    /// surroundings, however large it is, while white paper is saturated along with the paper
+    /// around it, or covers too much of the image to be a reflection.
+    fn glare(img: &GrayImage, region: &BoundingBox) -> f32 {
+        if img.width() == 0 || img.height() == 0 {
+            return 0.0;
+        }
+        let scale = (img.width() as f32 / Self::GLARE_ANALYSIS_WIDTH as f32).max(1.0);
+        let width = ((img.width() as f32 / scale).round() as u32).max(1);
+        let height = ((img.height() as f32 / scale).round() as u32).max(1);
+        let paper = ImageProcessor::max_filter(&imageops::resize(img, width, height, FilterType::Triangle), 2, 2);
+        let saturated: GrayImage = ImageBuffer::from_fn(width, height, |x, y| {
+            Luma([if paper.get_pixel(x, y)[0] >= Self::SATURATED { 255 } else { 0 }])
+        });
+
+        // Bounds and area of every saturated area
+        let labels = connected_components(&saturated, Connectivity::Eight, Luma([0u8]));
+        let mut areas: HashMap<u32, (u32, u32, u32, u32, u32)> = HashMap::new();
+        for (x, y, label) in labels.enumerate_pixels() {
+            if label[0] == 0 {
+                continue;
+            }
+            let area = areas.entry(label[0]).or_insert((x, y, x, y, 0));
+            *area = (area.0.min(x), area.1.min(y), area.2.max(x), area.3.max(y), area.4 + 1);
+        }
+
+        let max_area = Self::MAX_HOTSPOT_AREA * (width * height) as f32;
+        let ring = Self::HOTSPOT_RING;
+        let near = |x: u32, y: u32, label: u32| {
+            (y.saturating_sub(ring)..=(y + ring).min(height - 1))
+                .any(|j| (x.saturating_sub(ring)..=(x + ring).min(width - 1)).any(|i| labels.get_pixel(i, j)[0] == label))
+        };
+        let hotspots: HashSet<u32> = areas
+            .into_iter()
+            .filter(|(_, area)| (area.4 as f32) <= max_area)
+            .filter(|(label, (x0, y0, x1, y1, _))| {
+                let mut surroundings: Vec<u8> = Vec::new();
+                for y in y0.saturating_sub(ring)..=(y1 + ring).min(height - 1) {
+                    for x in x0.saturating_sub(ring)..=(x1 + ring).min(width - 1) {
+                        if saturated.get_pixel(x, y)[0] == 0 && near(x, y, *label) {
+                            surroundings.push(paper.get_pixel(x, y)[0]);
+                        }
+                    }
+                }
+                if surroundings.is_empty() {
+                    return false;
+                }
+                let middle = surroundings.len() / 2;
+                let (_, background, _) = surroundings.select_nth_unstable(middle);
+                background.saturating_add(Self::GLARE_CONTRAST) <= Self::SATURATED
+            })
+            .map(|(label, _)| label)
+            .collect();
+
+        let x0 = ((region.x as f32 / scale) as u32).min(width - 1);
+        let y0 = ((region.y as f32 / scale) as u32).min(height - 1);
+        let x1 = (((region.x + region.width) as f32 / scale).ceil() as u32).clamp(1, width);
+        let y1 = (((region.y + region.height) as f32 / scale).ceil() as u32).clamp(1, height);
+        let mut covered = 0;
+        let mut total = 0;
+        for y in y0..y1.max(y0 + 1) {
+            for x in x0..x1.max(x0 + 1) {
+                total += 1;
+                if hotspots.contains(&labels.get_pixel(x, y)[0]) {
+                    covered += 1;
+                }
+            }
+        }
+        covered as f32 / total as f32
+    }
+
+    /// Whether characters of the MRZ are cut by the edge of the image. The band reaches an edge
+    /// whenever its padding is clipped, so this only counts when ink runs into that edge.
+    fn is_cut(img: &GrayImage, band: &GrayImage, bounds: &BoundingBox) -> bool {
+        let threshold = otsu_level(band);
+        let inked = |pixels: &mut dyn Iterator<Item = u8>| {
+            let (ink, total) = pixels.fold((0usize, 0usize), |(ink, total), level| {
+                (ink + (level <= threshold) as usize, total + 1)
+            });
+            total > 0 && ink as f32 / total as f32 >= Self::CROPPED_INK_SHARE
+        };
+        let (width, height) = band.dimensions();
+        let column = |x: u32| (0..height).map(move |y| band.get_pixel(x, y)[0]);
+        let row = |y: u32| (0..width).map(move |x| band.get_pixel(x, y)[0]);
+
+        (bounds.x == 0 && inked(&mut column(0)))
+            || (bounds.x + bounds.width >= img.width() && inked(&mut column(width - 1)))
+            || (bounds.y == 0 && inked(&mut row(0)))
+            || (bounds.y + bounds.height >= img.height() && inked(&mut row(height - 1)))
+    }
+
+    /// Whether the document lies inside the image: an outline cut by the image edge can still
+    /// close into a quadrilateral along it
+    fn is_inside(img: &GrayImage, quad: &DocumentQuad) -> bool {
+        let margin_x = img.width() as f32 * Self::EDGE_MARGIN;
+        let margin_y = img.height() as f32 * Self::EDGE_MARGIN;
+        quad.corners.iter().all(|(x, y)| {
+            *x >= margin_x
+                && *y >= margin_y
+                && *x <= img.width() as f32 - margin_x
+                && *y <= img.height() as f32 - margin_y
+        })
+    }
+
+    /// Gray levels of the 5th and 95th percentiles
+    fn percentiles(img: &GrayImage) -> (u8, u8) {
+        let mut histogram = [0usize; 256];
+        for pixel in img.pixels() {
+            histogram[pixel[0] as usize] += 1;
+        }
+        let total: usize = histogram.iter().sum();
+        let level = |share: f32| {
+            let target = (total as f32 * share).ceil() as usize;
+            let mut cumulative = 0;
+            for (value, count) in histogram.iter().enumerate() {
+                cumulative += count;
+                if cumulative >= target.max(1) {
+                    return value as u8;
+                }
+            }
+            255
+        };
+        (level(0.05), level(0.95))
+    }
+
+    /// Resolution measured on the document when the page was warped to the dimensions of its
+    /// format; later rotations and deskewing keep the scale
+    fn measured_dpi(image: &ProcessedImage) -> Option<u32> {
+        image.transforms.iter().find_map(|transform| match transform {
+            ImageTransform::PerspectiveWarp { pixels_per_mm, .. } => Some((pixels_per_mm * 25.4).round() as u32),
+            _ => None,
+        })
+    }
+
+    /// Resolution from the width of the MRZ band, whose line length depends on the format:
+    /// 30 characters for TD1 (three lines, the tallest band), 36 for TD2 and 44 for TD3
+    fn estimate_dpi(bounds: &BoundingBox) -> u32 {
+        // The band is padded by 3% on either side when it is located
+        let width = bounds.width as f32 / 1.06;
+        let aspect_ratio = bounds.width as f32 / bounds.height.max(1) as f32;
+        let characters = if aspect_ratio < 6.0 {
+            30.0
+        } else if aspect_ratio < 8.5 {
+            36.0
+        } else {
+            44.0
+        };
+        (width / (characters / Self::MRZ_CHARACTERS_PER_INCH)).round() as u32
+    }
+}
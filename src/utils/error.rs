@@ -4,6 +4,7 @@ use std::fmt;
 #[derive(Debug)]
 pub enum PassportError {
     ImageProcessingError(String),
+    ImageQualityError(String),
     MrzExtractionError(String),
     MrzParsingError(String),
     SecurityFeatureDetectionError(String),
@@ -22,6 +23,7 @@ impl fmt::Display for PassportError {
             PassportError::ImageProcessingError(msg) => {
                 write!(f, "Image processing error: {}", msg)
             }
+            PassportError::ImageQualityError(msg) => write!(f, "Image quality error: {}", msg),
             PassportError::MrzExtractionError(msg) => write!(f, "MRZ extraction error: {}", msg),
             PassportError::MrzParsingError(msg) => write!(f, "MRZ parsing error: {}", msg),
             PassportError::SecurityFeatureDetectionError(msg) => {
@@ -1,6 +1,6 @@
 use crate::models::{ExpiryValidationResult, ValidationIssue, ValidationIssueType, VisualData};
 use crate::utils::PassportError;
-use chrono::{Local, NaiveDate};
+use chrono::Local;
 
 pub struct ExpiryValidator;
 
@@ -9,8 +9,8 @@ impl ExpiryValidator {
         let mut issues = Vec::new();
         let mut not_expired = false;
 
-        // Parse the expiry date
-        if let Some(date) = Self::parse_date(&visual_data.date_of_expiry) {
+        // An unknown expiry day or month extends validity to the end of the month or year
+        if let Some(date) = visual_data.date_of_expiry.latest_date() {
             // Get current date
             let today = Local::now().naive_local().date();
 
@@ -36,18 +36,4 @@ impl ExpiryValidator {
             issues,
         })
     }
-
-    // Parse a date string in the format "DD MM YYYY"
-    fn parse_date(date_str: &str) -> Option<NaiveDate> {
-        let parts: Vec<&str> = date_str.split_whitespace().collect();
-        if parts.len() != 3 {
-            return None;
-        }
-
-        let day = parts[0].parse::<u32>().ok()?;
-        let month = parts[1].parse::<u32>().ok()?;
-        let year = parts[2].parse::<i32>().ok()?;
-
-        NaiveDate::from_ymd_opt(year, month, day)
-    }
 }
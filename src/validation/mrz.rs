@@ -1,28 +1,367 @@
-use crate::models::{MrzData, MrzValidationResult, VisualData};
+use crate::models::{MrzData, MrzValidationResult, ValidationIssue, ValidationIssueType, VisualData};
+use crate::mrz::{CheckDigitCalculator, CheckedField, CountryRegistry, MrzLayout};
 use crate::utils::PassportError;
 
 pub struct MrzValidator;
 
 impl MrzValidator {
     pub fn validate(
-        _mrz_data: &MrzData,
-        _visual_data: &VisualData,
+        mrz_data: &MrzData,
+        visual_data: &VisualData,
     ) -> Result<MrzValidationResult, PassportError> {
-        let result = MrzValidationResult {
+        let mut result = MrzValidationResult {
             is_valid: true,
             document_number_check_valid: true,
             date_of_birth_check_valid: true,
             date_of_expiry_check_valid: true,
             personal_number_check_valid: true,
+            sex_check_valid: None,
             composite_check_valid: true,
+            corrections: mrz_data.corrections.clone(),
             issues: Vec::new(),
         };
 
-        // In a real implementation, the following would be validated:
-        // 1. Check digits against calculated values
-        // 2. Consistency between MRZ data and visual data
-        // 3. Format of fields (dates, document number, etc.)
+        let format = match &mrz_data.document_format {
+            Some(format) => format,
+            None => {
+                Self::fail_all(&mut result, "Unknown document format, check digits cannot be verified");
+                return Ok(result);
+            }
+        };
+
+        // Check digits are computed over the MRZ characters, so the raw lines are required
+        let lines: Vec<&str> = mrz_data.raw_mrz_lines.iter().map(|l| l.as_str()).collect();
+        if lines.len() != format.mrz_lines() {
+            Self::fail_all(&mut result, "Raw MRZ lines unavailable, check digits cannot be verified");
+            return Ok(result);
+        }
+
+        // Fields without a check digit in this format (e.g. the visa composite) stay valid
+        for rule in MrzLayout::check_digit_rules(format) {
+            let found = match rule.field {
+                CheckedField::DocumentNumber => mrz_data.check_digits.document_number_check,
+                CheckedField::DateOfBirth => mrz_data.check_digits.date_of_birth_check,
+                CheckedField::DateOfExpiry => mrz_data.check_digits.date_of_expiry_check,
+                CheckedField::PersonalNumber => mrz_data.check_digits.personal_number_check,
+                CheckedField::Composite => mrz_data.check_digits.composite_check,
+            };
+
+            let valid = match MrzLayout::check_digit_input(&lines, &rule) {
+                Some((data, _)) => match CheckDigitCalculator::compute(&data) {
+                    Some(_) if CheckDigitCalculator::verify(&data, found) => true,
+                    Some(expected) => {
+                        result.issues.push(Self::issue(format!(
+                            "{} check digit mismatch: expected '{}', found '{}'",
+                            rule.field.name(),
+                            expected,
+                            found
+                        )));
+                        false
+                    }
+                    None => {
+                        result.issues.push(Self::issue(format!(
+                            "{} contains characters outside the MRZ character set: {}",
+                            rule.field.name(),
+                            data
+                        )));
+                        false
+                    }
+                },
+                None => {
+                    result.issues.push(Self::issue(format!(
+                        "{} check digit could not be located in the MRZ",
+                        rule.field.name()
+                    )));
+                    false
+                }
+            };
+
+            match rule.field {
+                CheckedField::DocumentNumber => result.document_number_check_valid = valid,
+                CheckedField::DateOfBirth => result.date_of_birth_check_valid = valid,
+                CheckedField::DateOfExpiry => result.date_of_expiry_check_valid = valid,
+                CheckedField::PersonalNumber => result.personal_number_check_valid = valid,
+                CheckedField::Composite => result.composite_check_valid = valid,
+            }
+        }
+
+        // Check digits are verified on the corrected MRZ, so say which characters were changed
+        // for them to hold rather than report those fields as read cleanly
+        for correction in &mrz_data.corrections {
+            result.issues.push(Self::issue(format!(
+                "{} was corrected from the OCR read: '{}' -> '{}' at line {} position {}",
+                correction.field,
+                correction.original,
+                correction.corrected,
+                correction.line + 1,
+                correction.position + 1
+            )));
+        }
+
+        // Sex has no check digit, so compare meanings with the VIZ (e.g. H for hombre reads as M)
+        if let (Some(mrz_sex), Some(viz_sex)) = (mrz_data.sex, visual_data.sex) {
+            result.sex_check_valid = Some(mrz_sex == viz_sex);
+            if mrz_sex != viz_sex {
+                result.issues.push(Self::issue(format!(
+                    "Sex mismatch between MRZ and visual data: MRZ '{}', visual data '{}'",
+                    mrz_sex, viz_sex
+                )));
+            }
+        }
+
+        // Fields the parser could not read are never accepted, even when their check digit is
+        result.issues.extend(Self::unreadable_fields(mrz_data).into_iter().map(Self::issue));
+
+        let code_issues = Self::invalid_country_codes(mrz_data);
+        let codes_valid = code_issues.is_empty();
+        result.issues.extend(code_issues.into_iter().map(Self::issue));
+
+        result.is_valid = mrz_data.unreadable.is_empty()
+            && codes_valid
+            && result.sex_check_valid != Some(false)
+            && result.document_number_check_valid
+            && result.date_of_birth_check_valid
+            && result.date_of_expiry_check_valid
+            && result.personal_number_check_valid
+            && result.composite_check_valid;
 
         Ok(result)
     }
+
+    /// One message per field the parser left empty because it could not be read
+    pub fn unreadable_fields(mrz_data: &MrzData) -> Vec<String> {
+        mrz_data
+            .unreadable
+            .iter()
+            .map(|field| format!("{} is unreadable: {}", field.field, field.reason))
+            .collect()
+    }
+
+    /// Codes must be ICAO issuing state or nationality codes; unreadable ones are left to the
+    /// unreadable field report
+    pub fn invalid_country_codes(mrz_data: &MrzData) -> Vec<String> {
+        [("Issuing country", &mrz_data.issuing_country), ("Nationality", &mrz_data.nationality)]
+            .into_iter()
+            .filter(|(_, code)| !code.is_empty() && !CountryRegistry::is_valid(code))
+            .map(|(field, code)| format!("{} '{}' is not an ICAO country or organization code", field, code))
+            .collect()
+    }
+
+    fn fail_all(result: &mut MrzValidationResult, message: &str) {
+        result.is_valid = false;
+        result.document_number_check_valid = false;
+        result.date_of_birth_check_valid = false;
+        result.date_of_expiry_check_valid = false;
+        result.personal_number_check_valid = false;
+        result.composite_check_valid = false;
+        result.issues.push(Self::issue(message.to_string()));
+    }
+
+    fn issue(message: String) -> ValidationIssue {
+        ValidationIssue {
+            issue_type: ValidationIssueType::Mrz,
+            message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Sex;
+    use crate::mrz::MrzParser;
+    use std::collections::HashMap;
+
+    const TD3: [&str; 2] = [
+        "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<",
+        "L898902C36UTO7408122F1204159ZE184226B<<<<<10",
+    ];
+    const TD1: [&str; 3] = [
+        "I<UTOD231458907<<<<<<<<<<<<<<<",
+        "7408122F1204159UTO<<<<<<<<<<<6",
+        "ERIKSSON<<ANNA<MARIA<<<<<<<<<<",
+    ];
+    // Document number D23145890734 continues in the optional data, followed by its check digit
+    const TD1_OVERFLOW: [&str; 3] = [
+        "I<UTOD23145890<7349<<<<<<<<<<<",
+        "7408122F1204159UTO<<<<<<<<<<<6",
+        "ERIKSSON<<ANNA<MARIA<<<<<<<<<<",
+    ];
+    const TD2_OVERFLOW: [&str; 2] = [
+        "I<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<",
+        "D23145890<UTO7408122F12041597349<<<2",
+    ];
+
+    fn visual(mrz: &MrzData) -> VisualData {
+        VisualData {
+            document_format: mrz.document_format.clone(),
+            document_type: mrz.document_type.clone(),
+            issuing_country: mrz.issuing_country.clone(),
+            document_number: mrz.document_number.clone(),
+            name: format!("{} {}", mrz.given_names, mrz.surname),
+            surname: mrz.surname.clone(),
+            given_names: mrz.given_names.clone(),
+            nationality: mrz.nationality.clone(),
+            date_of_birth: mrz.date_of_birth.clone(),
+            sex: mrz.sex,
+            place_of_birth: None,
+            date_of_issue: Default::default(),
+            date_of_expiry: mrz.date_of_expiry.clone(),
+            authority: None,
+            personal_number: mrz.personal_number.clone(),
+            portrait: None,
+            signature: None,
+            secondary_portrait: None,
+            additional_fields: HashMap::new(),
+            confidences: Vec::new(),
+        }
+    }
+
+    fn validate(lines: &[&str]) -> MrzValidationResult {
+        let mrz = MrzParser::parse(lines).expect("MRZ parses");
+        MrzValidator::validate(&mrz, &visual(&mrz)).expect("validation runs")
+    }
+
+    /// Replace one character of an MRZ line
+    fn with_char(lines: &[&str], line: usize, position: usize, c: char) -> Vec<String> {
+        let mut lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        lines[line].replace_range(position..position + 1, &c.to_string());
+        lines
+    }
+
+    type Flag = fn(&MrzValidationResult) -> bool;
+
+    fn refs(lines: &[String]) -> Vec<&str> {
+        lines.iter().map(|l| l.as_str()).collect()
+    }
+
+    #[test]
+    fn accepts_td3_specimen() {
+        let result = validate(&TD3);
+        assert!(result.is_valid, "{:?}", result.issues);
+        assert!(result.document_number_check_valid);
+        assert!(result.date_of_birth_check_valid);
+        assert!(result.date_of_expiry_check_valid);
+        assert!(result.personal_number_check_valid);
+        assert!(result.composite_check_valid);
+        assert!(result.issues.is_empty());
+    }
+
+    #[test]
+    fn reports_each_mismatched_field() {
+        // Check digit positions on line 2 and the flag each one drives
+        let cases: [(usize, Flag, &str); 5] = [
+            (9, |r| r.document_number_check_valid, "Document number"),
+            (19, |r| r.date_of_birth_check_valid, "Date of birth"),
+            (27, |r| r.date_of_expiry_check_valid, "Date of expiry"),
+            (42, |r| r.personal_number_check_valid, "Personal number"),
+            (43, |r| r.composite_check_valid, "Composite"),
+        ];
+        for (position, flag, field) in cases {
+            let found = TD3[1].as_bytes()[position] as char;
+            let wrong = if found == '5' { '4' } else { '5' };
+            let lines = with_char(&TD3, 1, position, wrong);
+            let result = validate(&refs(&lines));
+            assert!(!result.is_valid, "{} mismatch accepted", field);
+            assert!(!flag(&result), "{} flag still set", field);
+            assert!(
+                result.issues.iter().any(|i| i.message.starts_with(&format!("{} check digit mismatch", field))),
+                "{:?}",
+                result.issues
+            );
+        }
+    }
+
+    #[test]
+    fn reports_a_changed_data_character() {
+        // 740812 read as 740813: the date of birth check digit no longer holds
+        let lines = with_char(&TD3, 1, 18, '3');
+        let result = validate(&refs(&lines));
+        assert!(!result.date_of_birth_check_valid);
+        assert!(!result.composite_check_valid);
+        assert!(result.document_number_check_valid);
+        assert!(result.date_of_expiry_check_valid);
+    }
+
+    #[test]
+    fn compares_sex_with_the_visual_data() {
+        let mrz = MrzParser::parse(&TD3).expect("MRZ parses");
+        let result = MrzValidator::validate(&mrz, &visual(&mrz)).expect("validation runs");
+        assert_eq!(result.sex_check_valid, Some(true));
+
+        let mut viz = visual(&mrz);
+        viz.sex = Some(Sex::Male);
+        let result = MrzValidator::validate(&mrz, &viz).expect("validation runs");
+        assert_eq!(result.sex_check_valid, Some(false));
+        assert!(!result.is_valid);
+        assert!(result.issues.iter().any(|i| i.message.starts_with("Sex mismatch")));
+
+        // Nothing to compare when the VIZ sex is unknown
+        viz.sex = None;
+        let result = MrzValidator::validate(&mrz, &viz).expect("validation runs");
+        assert_eq!(result.sex_check_valid, None);
+        assert!(result.is_valid);
+    }
+
+    #[test]
+    fn accepts_td1_specimen() {
+        let result = validate(&TD1);
+        assert!(result.is_valid, "{:?}", result.issues);
+    }
+
+    #[test]
+    fn verifies_overflowing_document_numbers() {
+        let td1 = validate(&TD1_OVERFLOW);
+        assert!(td1.is_valid, "{:?}", td1.issues);
+        assert!(td1.document_number_check_valid);
+
+        let td2 = validate(&TD2_OVERFLOW);
+        assert!(td2.is_valid, "{:?}", td2.issues);
+        assert!(td2.document_number_check_valid);
+
+        // The check digit at the end of the overflow protects the whole number
+        let lines = with_char(&TD1_OVERFLOW, 0, 18, '8');
+        let result = validate(&refs(&lines));
+        assert!(!result.document_number_check_valid);
+
+        let lines = with_char(&TD2_OVERFLOW, 1, 30, '5');
+        let result = validate(&refs(&lines));
+        assert!(!result.document_number_check_valid);
+    }
+
+    #[test]
+    fn rejects_characters_outside_the_mrz_charset() {
+        let mut mrz = MrzParser::parse(&TD3).expect("MRZ parses");
+        mrz.raw_mrz_lines[1].replace_range(2..3, "*");
+        let result = MrzValidator::validate(&mrz, &visual(&mrz)).expect("validation runs");
+        assert!(!result.is_valid);
+        assert!(!result.document_number_check_valid);
+        assert!(result
+            .issues
+            .iter()
+            .any(|i| i.message.starts_with("Document number contains characters outside the MRZ character set")));
+    }
+
+    #[test]
+    fn reports_unknown_country_codes() {
+        let mut mrz = MrzParser::parse(&TD3).expect("MRZ parses");
+        assert!(MrzValidator::invalid_country_codes(&mrz).is_empty());
+
+        mrz.nationality = "XYZ".to_string();
+        let result = MrzValidator::validate(&mrz, &visual(&mrz)).expect("validation runs");
+        assert!(!result.is_valid);
+        assert_eq!(
+            MrzValidator::invalid_country_codes(&mrz),
+            vec!["Nationality 'XYZ' is not an ICAO country or organization code".to_string()]
+        );
+    }
+
+    #[test]
+    fn fails_without_raw_lines() {
+        let mut mrz = MrzParser::parse(&TD3).expect("MRZ parses");
+        mrz.raw_mrz_lines.clear();
+        let result = MrzValidator::validate(&mrz, &visual(&mrz)).expect("validation runs");
+        assert!(!result.is_valid);
+        assert!(!result.composite_check_valid);
+    }
 }
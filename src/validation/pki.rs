@@ -21,7 +21,9 @@ impl PKIValidator {
                 date_of_birth_check_valid: true,
                 date_of_expiry_check_valid: true,
                 personal_number_check_valid: true,
+                sex_check_valid: None,
                 composite_check_valid: true,
+                corrections: vec![],
                 issues: vec![],
             },
             format_validation: FormatValidationResult {
@@ -1,8 +1,11 @@
 use crate::models::{
-    DocumentFormat, ExpiryValidationResult, FormatValidationResult, MrzData,
+    ExpiryValidationResult, FormatValidationResult, MrzData,
     MrzValidationResult, ValidationIssue, ValidationIssueType, ValidationResult, VisualData
 };
+use crate::mrz::{DocumentTypeRegistry, MrzLayout, Transliterator};
+use crate::processing::ProcessedImage;
 use crate::utils::PassportError;
+use crate::validation::MrzValidator;
 
 /// MRTD Verifier for validating Machine Readable Travel Documents
 /// according to ICAO Doc 9303 standards
@@ -17,7 +20,7 @@ impl MRTDVerifier {
     /// Verify an MRTD document according to ICAO Doc 9303 standards
     pub fn verify(
         &self,
-        _image_data: &[u8],
+        _image: &ProcessedImage,
         mrz_data: &MrzData,
         visual_data: &VisualData,
     ) -> Result<ValidationResult, PassportError> {
@@ -68,7 +71,7 @@ impl MRTDVerifier {
         }
 
         // Check date of birth
-        let date_of_birth_check_valid = mrz_data.date_of_birth == visual_data.date_of_birth;
+        let date_of_birth_check_valid = mrz_data.date_of_birth.same_date(&visual_data.date_of_birth);
         if !date_of_birth_check_valid {
             issues.push(ValidationIssue {
                 issue_type: ValidationIssueType::Mrz,
@@ -77,7 +80,7 @@ impl MRTDVerifier {
         }
 
         // Check date of expiry
-        let date_of_expiry_check_valid = mrz_data.date_of_expiry == visual_data.date_of_expiry;
+        let date_of_expiry_check_valid = mrz_data.date_of_expiry.same_date(&visual_data.date_of_expiry);
         if !date_of_expiry_check_valid {
             issues.push(ValidationIssue {
                 issue_type: ValidationIssueType::Mrz,
@@ -98,11 +101,42 @@ impl MRTDVerifier {
             });
         }
 
+        // Check sex, comparing meanings so localized VIZ labels (e.g. H for hombre) match the MRZ
+        let sex_check_valid = match (mrz_data.sex, visual_data.sex) {
+            (Some(mrz_sex), Some(viz_sex)) if mrz_sex != viz_sex => {
+                issues.push(ValidationIssue {
+                    issue_type: ValidationIssueType::Mrz,
+                    message: format!(
+                        "Sex mismatch between MRZ and visual data: MRZ '{}', visual data '{}'",
+                        mrz_sex, viz_sex
+                    ),
+                });
+                Some(false)
+            }
+            (Some(_), Some(_)) => Some(true),
+            // An unreadable sex is reported as such, not as a mismatch
+            _ => None,
+        };
+
+        // Check name, accepting every Doc 9303 transliteration of the VIZ name (e.g. MÜLLER as MUELLER)
+        let name_valid = Self::names_match(mrz_data, visual_data);
+        if !name_valid {
+            issues.push(ValidationIssue {
+                issue_type: ValidationIssueType::Mrz,
+                message: format!(
+                    "Name mismatch between MRZ and visual data: '{} {}' is not a valid transliteration of '{} {}'",
+                    mrz_data.surname, mrz_data.given_names, visual_data.surname, visual_data.given_names
+                ),
+            });
+        }
+
         // Check composite fields
         let composite_check_valid = document_number_check_valid
             && date_of_birth_check_valid
             && date_of_expiry_check_valid
-            && personal_number_check_valid;
+            && personal_number_check_valid
+            && sex_check_valid != Some(false)
+            && name_valid;
 
         Ok(MrzValidationResult {
             is_valid: composite_check_valid,
@@ -110,11 +144,33 @@ impl MRTDVerifier {
             date_of_birth_check_valid,
             date_of_expiry_check_valid,
             personal_number_check_valid,
+            sex_check_valid,
             composite_check_valid,
+            corrections: mrz_data.corrections.clone(),
             issues,
         })
     }
 
+    /// Compare the VIZ name with the MRZ name field, allowing for transliteration and truncation
+    fn names_match(mrz_data: &MrzData, visual_data: &VisualData) -> bool {
+        if visual_data.surname.trim().is_empty() {
+            return true;
+        }
+
+        // The raw name field tells whether the name was truncated to fit the MRZ
+        let lines: Vec<&str> = mrz_data.raw_mrz_lines.iter().map(|l| l.as_str()).collect();
+        let name_field = mrz_data
+            .document_format
+            .as_ref()
+            .and_then(|format| MrzLayout::name_span(format).extract(&lines))
+            .map(|field| field.to_string())
+            .unwrap_or_else(|| {
+                format!("{}<<{}<", mrz_data.surname, mrz_data.given_names).replace(' ', "<")
+            });
+
+        Transliterator::matches_name_field(&visual_data.surname, &visual_data.given_names, &name_field)
+    }
+
     /// Validate document format according to ICAO Doc 9303 standards
     fn validate_format(&self, mrz_data: &MrzData) -> Result<FormatValidationResult, PassportError> {
         let mut issues = Vec::new();
@@ -133,25 +189,73 @@ impl MRTDVerifier {
             });
         }
 
-        // Get valid document types for the format
-        let valid_doc_types = match mrz_data.document_format.as_ref().unwrap() {
-            DocumentFormat::TD1 => vec!["I", "ID"],
-            DocumentFormat::TD2 => vec!["I", "ID"],
-            DocumentFormat::TD3 => vec!["P", "PA"],
-            DocumentFormat::MRVA => vec!["V", "VA"],
-            DocumentFormat::MRVB => vec!["V", "VB"],
-        };
+        let format = mrz_data.document_format.as_ref().unwrap();
 
-        if !valid_doc_types.contains(&mrz_data.document_type.as_str()) {
+        // The document category must match the format and the code must be known for the issuer
+        match &mrz_data.document_type {
+            Some(document_type) if !document_type.fits_format(format) => {
+                issues.push(ValidationIssue {
+                    issue_type: ValidationIssueType::Format,
+                    message: format!(
+                        "Document type '{}' ({:?}) is not issued in format {:?}",
+                        document_type, document_type.category, format
+                    ),
+                });
+            }
+            Some(document_type) => match DocumentTypeRegistry::lookup(&mrz_data.issuing_country, &document_type.code) {
+                None => issues.push(ValidationIssue {
+                    issue_type: ValidationIssueType::Format,
+                    message: format!(
+                        "Document type '{}' for issuer '{}' is not in the document type registry",
+                        document_type, mrz_data.issuing_country
+                    ),
+                }),
+                Some(entry) if !entry.formats.contains(format) => issues.push(ValidationIssue {
+                    issue_type: ValidationIssueType::Format,
+                    message: format!("Invalid document type '{}' for format {:?}", document_type, format),
+                }),
+                Some(_) => {}
+            },
+            // Unreadable document types are reported with the other unreadable fields
+            None => {}
+        }
+
+        // Check the MRZ layout matches the format
+        if !mrz_data.raw_mrz_lines.is_empty()
+            && (mrz_data.raw_mrz_lines.len() != format.mrz_lines()
+                || mrz_data.raw_mrz_lines.iter().any(|l| l.len() != format.mrz_chars_per_line()))
+        {
             issues.push(ValidationIssue {
                 issue_type: ValidationIssueType::Format,
                 message: format!(
-                    "Invalid document type '{}' for format {:?}",
-                    mrz_data.document_type, mrz_data.document_format
+                    "MRZ layout does not match format {:?}: expected {} lines of {} characters",
+                    format,
+                    format.mrz_lines(),
+                    format.mrz_chars_per_line()
                 ),
             });
         }
 
+        // Visas carry optional data instead of a composite check digit, so only require it elsewhere
+        if format.has_composite_check() && !mrz_data.check_digits.composite_check.is_ascii_digit() {
+            issues.push(ValidationIssue {
+                issue_type: ValidationIssueType::Format,
+                message: format!("Composite check digit missing for format {:?}", format),
+            });
+        }
+
+        // Issuing state and nationality must be registered ICAO codes
+        issues.extend(MrzValidator::invalid_country_codes(mrz_data).into_iter().map(|message| ValidationIssue {
+            issue_type: ValidationIssueType::Format,
+            message,
+        }));
+
+        // Unreadable fields are reported rather than replaced, so the document cannot pass as complete
+        issues.extend(MrzValidator::unreadable_fields(mrz_data).into_iter().map(|message| ValidationIssue {
+            issue_type: ValidationIssueType::Format,
+            message,
+        }));
+
         // Check if all validations passed
         let is_valid = issues.is_empty();
         let correct_format = is_valid;
@@ -167,9 +271,21 @@ impl MRTDVerifier {
     fn validate_expiry(&self, mrz_data: &MrzData) -> Result<ExpiryValidationResult, PassportError> {
         let mut issues = Vec::new();
 
-        // Parse expiry date
-        let expiry_date = chrono::NaiveDate::parse_from_str(&mrz_data.date_of_expiry, "%y%m%d")
-            .map_err(|_| PassportError::InvalidDate("Invalid expiry date format".to_string()))?;
+        // An unknown expiry day or month extends validity to the end of the month or year
+        let expiry_date = match mrz_data.date_of_expiry.latest_date() {
+            Some(date) => date,
+            None => {
+                issues.push(ValidationIssue {
+                    issue_type: ValidationIssueType::Expiry,
+                    message: format!("Expiry date '{}' could not be read", mrz_data.date_of_expiry.raw),
+                });
+                return Ok(ExpiryValidationResult {
+                    is_valid: false,
+                    not_expired: false,
+                    issues,
+                });
+            }
+        };
 
         // Check if document is expired
         let today = chrono::Local::now().naive_local().date();